@@ -0,0 +1,67 @@
+use serde::Deserialize;
+use std::fs::File;
+use std::path::PathBuf;
+
+/// User settings, read from `config.yaml` in comboman's config directory.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub history: HistoryConfig,
+    pub trash: TrashConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// Commands to leave out of the history pane: globs such as `ls*`, or
+    /// regexes written between slashes, e.g. `/^git (status|diff)$/`.
+    pub ignore: Vec<String>,
+    /// Show a run of identical commands once.
+    pub collapse_duplicates: bool,
+    /// Show each command once, at its most recent position.
+    pub dedupe: bool,
+    /// Idle time, in minutes, that separates two sessions of commands.
+    pub session_gap_minutes: i64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            ignore: ["ls", "ll", "clear", "exit", "history", "comboman*"]
+                .map(String::from)
+                .to_vec(),
+            collapse_duplicates: true,
+            dedupe: false,
+            session_gap_minutes: 30,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TrashConfig {
+    /// Days a deleted combo stays in the trash before it is purged.
+    pub retention_days: i64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        TrashConfig { retention_days: 30 }
+    }
+}
+
+fn get_config_file_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("comboman").join("config.yaml"))
+}
+
+/// Load the config file, falling back to defaults if it is missing or invalid.
+pub fn load_config() -> Config {
+    let Some(path) = get_config_file_path().filter(|p| p.exists()) else {
+        return Config::default();
+    };
+    let f = File::open(&path).expect("Cannot open config file");
+    serde_yaml::from_reader(f).unwrap_or_else(|e| {
+        eprintln!("Ignoring invalid config file {}: {e}", path.display());
+        Config::default()
+    })
+}
@@ -0,0 +1,69 @@
+use super::{read_paged, HistoryFilter, HistorySource};
+use crate::types::HistoryEntry;
+use rusqlite::{Connection, OpenFlags};
+use std::path::{Path, PathBuf};
+
+/// Atuin's SQLite database, which records the cwd, exit code, duration,
+/// hostname and session of each command.
+pub struct AtuinHistory {
+    pub path: PathBuf,
+}
+
+impl HistorySource for AtuinHistory {
+    fn read(&self, n: usize, filter: &HistoryFilter) -> Vec<HistoryEntry> {
+        read_atuin_db(&self.path, n, filter)
+    }
+}
+
+/// Location of Atuin's history database, honoring `ATUIN_DB_PATH`.
+pub fn default_db_path() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("ATUIN_DB_PATH") {
+        return Some(PathBuf::from(shellexpand::tilde(&path).into_owned()));
+    }
+    dirs::data_dir().map(|d| d.join("atuin").join("history.db"))
+}
+
+/// Whether history should come from Atuin: it is hooked into the calling
+/// shell (which exports `ATUIN_SESSION`) and its database exists.
+pub fn available() -> bool {
+    std::env::var_os("ATUIN_SESSION").is_some() && default_db_path().is_some_and(|p| p.exists())
+}
+
+fn read_atuin_db(path: &Path, n: usize, filter: &HistoryFilter) -> Vec<HistoryEntry> {
+    let conn = match Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY) {
+        Ok(conn) => conn,
+        Err(_) => return vec![],
+    };
+    // Older Atuin databases have no soft-delete column.
+    let query = "SELECT timestamp, duration, exit, command, cwd, session, hostname
+                 FROM history WHERE deleted_at IS NULL ORDER BY timestamp DESC LIMIT ?1 OFFSET ?2";
+    let fallback = "SELECT timestamp, duration, exit, command, cwd, session, hostname
+                    FROM history ORDER BY timestamp DESC LIMIT ?1 OFFSET ?2";
+    let mut stmt = match conn.prepare(query).or_else(|_| conn.prepare(fallback)) {
+        Ok(stmt) => stmt,
+        Err(_) => return vec![],
+    };
+    read_paged(n, filter, |limit, offset| {
+        let rows = stmt.query_map([limit as i64, offset as i64], |row| {
+            // Atuin stores times and durations in nanoseconds, and records
+            // unknown durations and exit codes as -1.
+            let timestamp: i64 = row.get(0)?;
+            let duration: i64 = row.get(1)?;
+            let exit: i64 = row.get(2)?;
+            Ok(HistoryEntry {
+                timestamp: Some(timestamp / 1_000_000_000),
+                duration: (duration >= 0).then_some(duration / 1_000_000_000),
+                exit_code: (exit >= 0).then_some(exit as i32),
+                command: row.get(3)?,
+                cwd: row.get(4)?,
+                session: row.get(5)?,
+                hostname: row.get(6)?,
+                ..Default::default()
+            })
+        });
+        match rows {
+            Ok(rows) => rows.filter_map(Result::ok).collect(),
+            Err(_) => vec![],
+        }
+    })
+}
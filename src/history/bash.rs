@@ -0,0 +1,93 @@
+use super::{read_tail, HistoryFilter, HistorySource, TextFormat};
+use crate::types::HistoryEntry;
+use std::path::PathBuf;
+
+/// bash's `~/.bash_history`, optionally with `HISTTIMEFORMAT` timestamps.
+pub struct BashHistory {
+    pub path: PathBuf,
+}
+
+const FORMAT: TextFormat = TextFormat {
+    metafied: false,
+    parse: parse_bash,
+    first_entry,
+};
+
+impl HistorySource for BashHistory {
+    fn read(&self, n: usize, filter: &HistoryFilter) -> Vec<HistoryEntry> {
+        read_tail(&self.path, n, &FORMAT, filter)
+    }
+}
+
+/// Parse bash history. When `HISTTIMEFORMAT` is set, bash precedes each
+/// entry with a `#<start>` comment line; in that case lines without their
+/// own timestamp belong to the previous (multi-line) entry, as bash itself
+/// reads them back.
+fn parse_bash(lines: Vec<String>) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = Vec::new();
+    let mut pending_timestamp = None;
+    let mut has_timestamps = false;
+    for line in lines {
+        if let Some(ts) = parse_bash_timestamp(&line) {
+            pending_timestamp = Some(ts);
+            has_timestamps = true;
+            continue;
+        }
+        match entries.last_mut() {
+            Some(entry) if has_timestamps && pending_timestamp.is_none() => {
+                entry.command.push('\n');
+                entry.command.push_str(&line);
+            }
+            _ => entries.push(HistoryEntry {
+                command: line,
+                timestamp: pending_timestamp.take(),
+                ..Default::default()
+            }),
+        }
+    }
+    entries
+}
+
+/// With timestamps, entries start at a `#<start>` line; without them every
+/// line is an entry of its own.
+fn first_entry(lines: &[String]) -> usize {
+    lines
+        .iter()
+        .position(|l| parse_bash_timestamp(l).is_some())
+        .unwrap_or(0)
+}
+
+/// The start time recorded by a `#<start>` comment line.
+fn parse_bash_timestamp(line: &str) -> Option<i64> {
+    line.strip_prefix('#')?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::lines;
+    use super::*;
+
+    #[test]
+    fn joins_multi_line_entries_after_timestamps() {
+        let text = "#1700000000\necho one\n#1700000005\ncat <<EOF\nhello\nEOF\n";
+        let entries = parse_bash(lines(text));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].command, "echo one");
+        assert_eq!(entries[0].timestamp, Some(1700000000));
+        assert_eq!(entries[1].command, "cat <<EOF\nhello\nEOF");
+        assert_eq!(entries[1].timestamp, Some(1700000005));
+    }
+
+    #[test]
+    fn without_timestamps_every_line_is_an_entry() {
+        let entries = parse_bash(lines("cat <<EOF\nhello\nEOF\n"));
+        let commands: Vec<&str> = entries.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, ["cat <<EOF", "hello", "EOF"]);
+    }
+
+    #[test]
+    fn first_entry_starts_at_a_timestamp() {
+        assert_eq!(first_entry(&lines("hello\nEOF\n#1700000010\nls")), 2);
+        assert_eq!(first_entry(&lines("ls\npwd")), 0);
+    }
+}
@@ -0,0 +1,296 @@
+use crate::config::HistoryConfig;
+use crate::types::HistoryEntry;
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
+use regex::Regex;
+use std::collections::HashSet;
+
+enum Pattern {
+    Glob(glob::Pattern),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn matches(&self, command: &str) -> bool {
+        match self {
+            Pattern::Glob(p) => p.matches(command),
+            Pattern::Regex(r) => r.is_match(command),
+        }
+    }
+}
+
+/// Removes noise from history before it is limited and shown.
+/// Entries that lack the timestamp, directory or session a scope asks for
+/// are dropped.
+#[derive(Default)]
+pub struct HistoryFilter {
+    ignore: Vec<Pattern>,
+    collapse_duplicates: bool,
+    dedupe: bool,
+    /// Only keep commands started at or after this Unix timestamp.
+    pub since: Option<i64>,
+    /// Only keep commands started before this Unix timestamp.
+    pub until: Option<i64>,
+    /// Only keep commands run in this directory or below it.
+    pub cwd: Option<String>,
+    /// Only keep commands from one of these shell sessions.
+    pub sessions: Vec<String>,
+}
+
+impl HistoryFilter {
+    pub fn from_config(config: &HistoryConfig) -> HistoryFilter {
+        let ignore = config
+            .ignore
+            .iter()
+            .filter_map(|p| {
+                let compiled = match p.strip_prefix('/').and_then(|r| r.strip_suffix('/')) {
+                    Some(re) => Regex::new(re).map(Pattern::Regex).map_err(|e| e.to_string()),
+                    None => glob::Pattern::new(p)
+                        .map(Pattern::Glob)
+                        .map_err(|e| e.to_string()),
+                };
+                compiled
+                    .map_err(|e| eprintln!("Ignoring invalid history pattern '{p}': {e}"))
+                    .ok()
+            })
+            .collect();
+        HistoryFilter {
+            ignore,
+            collapse_duplicates: config.collapse_duplicates,
+            dedupe: config.dedupe,
+            ..Default::default()
+        }
+    }
+
+    /// Whether `entry` falls within the time range, directory and sessions
+    /// this filter is scoped to.
+    fn in_scope(&self, entry: &HistoryEntry) -> bool {
+        let in_time = match entry.timestamp {
+            Some(t) => self.since.is_none_or(|s| t >= s) && self.until.is_none_or(|u| t < u),
+            None => self.since.is_none() && self.until.is_none(),
+        };
+        let in_dir = match (&self.cwd, &entry.cwd) {
+            (None, _) => true,
+            (Some(dir), Some(cwd)) => {
+                cwd == dir || cwd.starts_with(&format!("{}/", dir.trim_end_matches('/')))
+            }
+            (Some(_), None) => false,
+        };
+        let in_session = self.sessions.is_empty()
+            || entry.session.as_ref().is_some_and(|s| self.sessions.contains(s));
+        in_time && in_dir && in_session
+    }
+
+    /// Whether `entries` reach back before `since`, so that no older entry
+    /// can pass the filter and reading further back is pointless.
+    pub fn reaches_since(&self, entries: &[HistoryEntry]) -> bool {
+        let oldest = entries.iter().filter_map(|e| e.timestamp).min();
+        matches!((self.since, oldest), (Some(since), Some(oldest)) if oldest < since)
+    }
+
+    /// Filter `entries` (oldest first). Duplicates keep their newest entry,
+    /// so applying this to a longer tail of the same history never changes
+    /// which of the newer entries survive.
+    pub fn apply(&self, entries: Vec<HistoryEntry>) -> Vec<HistoryEntry> {
+        let mut kept: Vec<HistoryEntry> = entries
+            .into_iter()
+            .filter(|e| {
+                let command = e.command.trim();
+                !command.is_empty()
+                    && !self.ignore.iter().any(|p| p.matches(command))
+                    && self.in_scope(e)
+            })
+            .collect();
+        if self.collapse_duplicates {
+            let mut collapsed: Vec<HistoryEntry> = Vec::with_capacity(kept.len());
+            for entry in kept {
+                match collapsed.last_mut() {
+                    Some(last) if last.command == entry.command => *last = entry,
+                    _ => collapsed.push(entry),
+                }
+            }
+            kept = collapsed;
+        }
+        if self.dedupe {
+            let mut seen = HashSet::new();
+            kept.reverse();
+            kept.retain(|e| seen.insert(e.command.clone()));
+            kept.reverse();
+        }
+        kept
+    }
+}
+
+/// Parse a `--since`/`--until` argument into a Unix timestamp. Accepts a
+/// duration before now such as `90s`, `30m`, `2h`, `1d` or `1w`, or a local
+/// date and time: `2024-01-31`, `2024-01-31 14:00` or `2024-01-31T14:00:00`.
+pub fn parse_time(spec: &str) -> Option<i64> {
+    let spec = spec.trim();
+    if let Some(unit) = spec.chars().last().filter(|c| c.is_ascii_alphabetic()) {
+        if let Ok(amount) = spec[..spec.len() - 1].parse::<i64>() {
+            let ago = match unit {
+                's' => Duration::seconds(amount),
+                'm' => Duration::minutes(amount),
+                'h' => Duration::hours(amount),
+                'd' => Duration::days(amount),
+                'w' => Duration::weeks(amount),
+                _ => return None,
+            };
+            return Some((Local::now() - ago).timestamp());
+        }
+    }
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(spec, f).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(spec, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })?;
+    Local.from_local_datetime(&naive).earliest().map(|t| t.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(command: &str, timestamp: i64) -> HistoryEntry {
+        HistoryEntry {
+            command: command.to_string(),
+            timestamp: Some(timestamp),
+            ..Default::default()
+        }
+    }
+
+    fn configured(ignore: &[&str], collapse_duplicates: bool, dedupe: bool) -> HistoryFilter {
+        HistoryFilter::from_config(&HistoryConfig {
+            ignore: ignore.iter().map(|p| p.to_string()).collect(),
+            collapse_duplicates,
+            dedupe,
+            ..Default::default()
+        })
+    }
+
+    fn kept(entries: &[HistoryEntry]) -> Vec<(&str, Option<i64>)> {
+        entries
+            .iter()
+            .map(|e| (e.command.as_str(), e.timestamp))
+            .collect()
+    }
+
+    #[test]
+    fn ignores_globs_and_slashed_regexes() {
+        let filter = configured(&["ls*", "/^git (status|diff)$/", "/(/"], false, false);
+        let entries = [
+            "ls -la",
+            "  lsblk",
+            "git status",
+            "git status -s",
+            "git diff",
+            "make",
+            " ",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, c)| entry(c, i as i64))
+        .collect();
+        assert_eq!(
+            kept(&filter.apply(entries)),
+            [("git status -s", Some(3)), ("make", Some(5))]
+        );
+    }
+
+    #[test]
+    fn collapses_a_run_of_duplicates_into_its_last_entry() {
+        let filter = configured(&[], true, false);
+        let entries = vec![
+            entry("make", 1),
+            entry("make", 2),
+            entry("ls", 3),
+            entry("make", 4),
+        ];
+        assert_eq!(
+            kept(&filter.apply(entries)),
+            [("make", Some(2)), ("ls", Some(3)), ("make", Some(4))]
+        );
+    }
+
+    #[test]
+    fn dedupe_keeps_the_newest_duplicate() {
+        let filter = configured(&[], false, true);
+        let entries = vec![
+            entry("make", 1),
+            entry("ls", 2),
+            entry("make", 3),
+            entry("ls", 4),
+        ];
+        assert_eq!(
+            kept(&filter.apply(entries.clone())),
+            [("make", Some(3)), ("ls", Some(4))]
+        );
+        // A longer tail of the same history keeps the same newer entries.
+        let mut longer = vec![entry("ls", 0)];
+        longer.extend(entries);
+        assert_eq!(
+            kept(&filter.apply(longer)),
+            [("make", Some(3)), ("ls", Some(4))]
+        );
+    }
+
+    #[test]
+    fn since_is_inclusive_and_until_exclusive() {
+        let untimed = HistoryEntry {
+            command: "make".to_string(),
+            ..Default::default()
+        };
+        let mut entries: Vec<HistoryEntry> = (1..=5).map(|t| entry("make", t)).collect();
+        entries.push(untimed);
+        assert_eq!(HistoryFilter::default().apply(entries.clone()).len(), 6);
+        let filter = HistoryFilter {
+            since: Some(2),
+            until: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(
+            kept(&filter.apply(entries)),
+            [("make", Some(2)), ("make", Some(3))]
+        );
+    }
+
+    #[test]
+    fn reaches_since_once_an_entry_is_older() {
+        let filter = HistoryFilter {
+            since: Some(3),
+            ..Default::default()
+        };
+        assert!(!filter.reaches_since(&[entry("a", 3), entry("b", 5)]));
+        assert!(filter.reaches_since(&[entry("a", 5), entry("b", 2)]));
+        assert!(!filter.reaches_since(&[]));
+        assert!(!HistoryFilter::default().reaches_since(&[entry("a", 2)]));
+    }
+
+    #[test]
+    fn parses_dates_and_durations() {
+        let local = |h, m, s| {
+            Local
+                .with_ymd_and_hms(2024, 1, 31, h, m, s)
+                .unwrap()
+                .timestamp()
+        };
+        assert_eq!(parse_time("2024-01-31"), Some(local(0, 0, 0)));
+        assert_eq!(parse_time("2024-01-31 14:00"), Some(local(14, 0, 0)));
+        assert_eq!(parse_time(" 2024-01-31 14:00:05 "), Some(local(14, 0, 5)));
+        assert_eq!(parse_time("2024-01-31T14:00:05"), Some(local(14, 0, 5)));
+        assert_eq!(parse_time("2024-01-31T14:00"), Some(local(14, 0, 0)));
+
+        let ago = |spec| Local::now().timestamp() - parse_time(spec).unwrap();
+        assert!((90..92).contains(&ago("90s")));
+        assert!((1800..1802).contains(&ago("30m")));
+        assert!((7200..7202).contains(&ago("2h")));
+        assert!((86400..86402).contains(&ago("1d")));
+        assert!((604800..604802).contains(&ago("1w")));
+
+        for spec in ["2y", "yesterday", "", "2024-13-01", "14:00"] {
+            assert_eq!(parse_time(spec), None, "{spec}");
+        }
+    }
+}
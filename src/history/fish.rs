@@ -0,0 +1,113 @@
+use super::{plain_entry, read_tail, HistoryFilter, HistorySource, TextFormat};
+use crate::types::HistoryEntry;
+use std::path::PathBuf;
+
+/// fish's `fish_history`.
+pub struct FishHistory {
+    pub path: PathBuf,
+}
+
+const FORMAT: TextFormat = TextFormat {
+    metafied: false,
+    parse: parse_fish,
+    first_entry,
+};
+
+impl HistorySource for FishHistory {
+    fn read(&self, n: usize, filter: &HistoryFilter) -> Vec<HistoryEntry> {
+        read_tail(&self.path, n, &FORMAT, filter)
+    }
+}
+
+/// Parse fish's YAML-like `fish_history`:
+///
+/// ```text
+/// - cmd: cp a b
+///   when: 1697000000
+///   paths:
+///     - a
+/// ```
+fn parse_fish(lines: Vec<String>) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = Vec::new();
+    let mut in_paths = false;
+    for line in lines {
+        if let Some(cmd) = line.strip_prefix("- cmd: ") {
+            entries.push(plain_entry(&unescape_fish(cmd)));
+            in_paths = false;
+            continue;
+        }
+        let Some(entry) = entries.last_mut() else {
+            continue;
+        };
+        if let Some(when) = line.strip_prefix("  when: ") {
+            entry.timestamp = when.trim().parse().ok();
+            in_paths = false;
+        } else if line.trim_end() == "  paths:" {
+            in_paths = true;
+        } else if let Some(path) = line.strip_prefix("    - ").filter(|_| in_paths) {
+            entry.paths.push(unescape_fish(path));
+        }
+    }
+    entries
+}
+
+/// Undo fish's history escaping, which writes newlines as `\n` and
+/// backslashes as `\\`.
+fn unescape_fish(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn first_entry(lines: &[String]) -> usize {
+    lines
+        .iter()
+        .position(|l| l.starts_with("- cmd: "))
+        .unwrap_or(lines.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::lines;
+    use super::*;
+
+    #[test]
+    fn parses_entries_with_paths() {
+        let text = "- cmd: cp a b\n  when: 1697000000\n  paths:\n    - a\n    - b\n\
+                    - cmd: ls\n  when: 1697000001\n";
+        let entries = parse_fish(lines(text));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].command, "cp a b");
+        assert_eq!(entries[0].timestamp, Some(1697000000));
+        assert_eq!(entries[0].paths, ["a", "b"]);
+        assert_eq!(entries[1].timestamp, Some(1697000001));
+        assert!(entries[1].paths.is_empty());
+    }
+
+    #[test]
+    fn unescapes_newlines_and_backslashes() {
+        let entries = parse_fish(lines(r"- cmd: printf 'a\nb' \\ c\t"));
+        assert_eq!(entries[0].command, "printf 'a\nb' \\ c\\t");
+    }
+
+    #[test]
+    fn first_entry_skips_the_rest_of_an_entry() {
+        assert_eq!(first_entry(&lines("  paths:\n    - a\n- cmd: ls")), 2);
+        assert_eq!(first_entry(&lines("  when: 1697000000")), 1);
+    }
+}
@@ -0,0 +1,30 @@
+use super::{read_tail, HistoryFilter, HistorySource, TextFormat};
+use crate::journal::parse_record;
+use crate::types::HistoryEntry;
+use std::path::PathBuf;
+
+/// comboman's own journal, which records the cwd, exit status, duration and
+/// session of every command run in a shell with `comboman init` hooks.
+pub struct JournalHistory {
+    pub path: PathBuf,
+}
+
+const FORMAT: TextFormat = TextFormat {
+    metafied: false,
+    parse: parse_journal,
+    first_entry,
+};
+
+impl HistorySource for JournalHistory {
+    fn read(&self, n: usize, filter: &HistoryFilter) -> Vec<HistoryEntry> {
+        read_tail(&self.path, n, &FORMAT, filter)
+    }
+}
+
+fn parse_journal(lines: Vec<String>) -> Vec<HistoryEntry> {
+    lines.iter().filter_map(|line| parse_record(line)).collect()
+}
+
+fn first_entry(_lines: &[String]) -> usize {
+    0
+}
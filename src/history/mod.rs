@@ -0,0 +1,338 @@
+mod atuin;
+mod bash;
+mod filter;
+mod fish;
+mod journal;
+mod nushell;
+mod pwsh;
+mod zsh;
+
+use crate::types::HistoryEntry;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+pub use atuin::AtuinHistory;
+pub use bash::BashHistory;
+pub use filter::{parse_time, HistoryFilter};
+pub use fish::FishHistory;
+pub use journal::JournalHistory;
+pub use nushell::NushellHistory;
+pub use pwsh::PwshHistory;
+pub use zsh::ZshHistory;
+
+/// Somewhere commands can be read back from, such as a shell history file.
+pub trait HistorySource {
+    /// Read up to `n` recent entries (newest last) that pass `filter`.
+    fn read(&self, n: usize, filter: &HistoryFilter) -> Vec<HistoryEntry>;
+}
+
+/// Shells whose history formats comboman can read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Nushell,
+    Pwsh,
+}
+
+impl Shell {
+    /// Recognise a shell from its name or path, e.g. `/usr/bin/zsh`.
+    pub fn from_name(name: &str) -> Option<Shell> {
+        let base = name.rsplit('/').next().unwrap_or(name);
+        match base.trim_end_matches(".exe") {
+            "bash" | "sh" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "fish" => Some(Shell::Fish),
+            "nu" | "nushell" => Some(Shell::Nushell),
+            "pwsh" | "powershell" => Some(Shell::Pwsh),
+            _ => None,
+        }
+    }
+
+    /// The shell named by `$COMBOMAN_SHELL`, else `$SHELL`, else bash.
+    pub fn detect() -> Shell {
+        std::env::var("COMBOMAN_SHELL")
+            .ok()
+            .and_then(|s| Shell::from_name(&s))
+            .or_else(|| std::env::var("SHELL").ok().and_then(|s| Shell::from_name(&s)))
+            .unwrap_or(Shell::Bash)
+    }
+
+    /// The shell's default history file.
+    pub fn default_history_file(self) -> PathBuf {
+        let expand = |p: &str| PathBuf::from(shellexpand::tilde(p).into_owned());
+        let histfile = std::env::var("HISTFILE");
+        match self {
+            Shell::Bash => expand(histfile.as_deref().unwrap_or("~/.bash_history")),
+            Shell::Zsh => expand(histfile.as_deref().unwrap_or("~/.zsh_history")),
+            Shell::Fish => expand("~/.local/share/fish/fish_history"),
+            Shell::Nushell => NushellHistory::default_path(),
+            Shell::Pwsh => dirs::data_dir()
+                .unwrap_or_default()
+                .join("powershell")
+                .join("PSReadLine")
+                .join("ConsoleHost_history.txt"),
+        }
+    }
+
+    /// A source reading this shell's history format from `path`.
+    pub fn source(self, path: PathBuf) -> Box<dyn HistorySource> {
+        match self {
+            Shell::Bash => Box::new(BashHistory { path }),
+            Shell::Zsh => Box::new(ZshHistory { path }),
+            Shell::Fish => Box::new(FishHistory { path }),
+            Shell::Nushell => Box::new(NushellHistory { path }),
+            Shell::Pwsh => Box::new(PwshHistory { path }),
+        }
+    }
+}
+
+/// Build a source from a format name (a shell, `atuin` or `journal`) and an
+/// optional path, which defaults to that format's usual location.
+pub fn source_for(kind: &str, path: Option<PathBuf>) -> Option<Box<dyn HistorySource>> {
+    if kind == "atuin" {
+        let path = path.or_else(atuin::default_db_path)?;
+        return Some(Box::new(AtuinHistory { path }));
+    }
+    if kind == "journal" {
+        let path = path.unwrap_or_else(crate::journal::journal_path);
+        return Some(Box::new(JournalHistory { path }));
+    }
+    let shell = Shell::from_name(kind)?;
+    Some(shell.source(path.unwrap_or_else(|| shell.default_history_file())))
+}
+
+/// Parse a `--source` argument: either `<kind>:<path>` or a bare path whose
+/// format is guessed from its file name, falling back to the user's shell.
+pub fn parse_source_spec(spec: &str) -> Option<Box<dyn HistorySource>> {
+    if let Some((kind, path)) = spec.split_once(':') {
+        if let Some(source) = source_for(kind, Some(expand_path(path))) {
+            return Some(source);
+        }
+    }
+    let path = expand_path(spec);
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    let kind = if name.contains("zsh") {
+        "zsh"
+    } else if name.contains("fish") {
+        "fish"
+    } else if name.contains("bash") {
+        "bash"
+    } else if name.starts_with("consolehost") {
+        "pwsh"
+    } else if name.starts_with("history.sqlite3") || name == "history.txt" {
+        "nu"
+    } else if name == "history.db" {
+        "atuin"
+    } else if name == "journal.tsv" {
+        "journal"
+    } else {
+        return Some(Shell::detect().source(path));
+    };
+    source_for(kind, Some(path))
+}
+
+/// The source used when none is given: comboman's journal or Atuin if
+/// either is hooked into the calling shell, otherwise the detected shell's
+/// history file.
+pub fn default_source() -> Box<dyn HistorySource> {
+    let journal = crate::journal::journal_path();
+    if std::env::var_os("COMBOMAN_SESSION").is_some() && journal.exists() {
+        return Box::new(JournalHistory { path: journal });
+    }
+    if atuin::available() {
+        if let Some(path) = atuin::default_db_path() {
+            return Box::new(AtuinHistory { path });
+        }
+    }
+    let shell = Shell::detect();
+    shell.source(shell.default_history_file())
+}
+
+/// Read up to `n` recent entries (newest last) from all `sources`, after
+/// removing those rejected by `filter`. Entries are merged by timestamp when
+/// every entry has one, and otherwise kept in source order.
+pub fn import_history(
+    sources: &[Box<dyn HistorySource>],
+    n: usize,
+    filter: &HistoryFilter,
+) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = sources.iter().flat_map(|s| s.read(n, filter)).collect();
+    if sources.len() > 1 {
+        if entries.iter().all(|e| e.timestamp.is_some()) {
+            entries.sort_by_key(|e| e.timestamp);
+        }
+        entries = filter.apply(entries);
+    }
+    entries.into_iter().rev().take(n).rev().collect()
+}
+
+/// How to parse a plaintext history format from an arbitrary point in the
+/// file, used by [`read_tail`].
+pub(super) struct TextFormat {
+    /// Whether the file is zsh-metafied.
+    pub metafied: bool,
+    pub parse: fn(Vec<String>) -> Vec<HistoryEntry>,
+    /// Index of the first line that certainly starts an entry, given lines
+    /// which may begin part-way through one.
+    pub first_entry: fn(&[String]) -> usize,
+}
+
+/// Size of the first chunk read from the end of a history file.
+const TAIL_CHUNK: u64 = 64 * 1024;
+
+/// Read the last `n` entries (newest last) of a plaintext history file that
+/// pass `filter`.
+/// Reads backwards from the end in growing chunks until `n` complete entries
+/// are found, so the cost is bounded by `n` rather than by the file size.
+/// With `--since`, it also stops once a chunk reaches back before that time.
+pub(super) fn read_tail(
+    path: &Path,
+    n: usize,
+    format: &TextFormat,
+    filter: &HistoryFilter,
+) -> Vec<HistoryEntry> {
+    let Ok(mut file) = File::open(path) else {
+        return vec![];
+    };
+    let Ok(len) = file.metadata().map(|m| m.len()) else {
+        return vec![];
+    };
+    let mut window = TAIL_CHUNK;
+    loop {
+        let start = len.saturating_sub(window);
+        let mut bytes = Vec::with_capacity((len - start) as usize);
+        if file.seek(SeekFrom::Start(start)).is_err()
+            || file.by_ref().take(len - start).read_to_end(&mut bytes).is_err()
+        {
+            return vec![];
+        }
+        let entries = if start == 0 {
+            (format.parse)(decode_lines(&bytes, format.metafied))
+        } else {
+            // Drop the line the chunk starts in, then anything before the
+            // first entry that is known to be complete.
+            let Some(newline) = bytes.iter().position(|&b| b == b'\n') else {
+                window *= 2;
+                continue;
+            };
+            let mut lines = decode_lines(&bytes[newline + 1..], format.metafied);
+            let first = (format.first_entry)(&lines);
+            lines.drain(..first);
+            (format.parse)(lines)
+        };
+        let complete = start == 0 || filter.reaches_since(&entries);
+        let entries = filter.apply(entries);
+        if complete || entries.len() >= n {
+            return entries.into_iter().rev().take(n).rev().collect();
+        }
+        window *= 2;
+    }
+}
+
+/// Read the last `n` entries (newest last) that pass `filter` from a
+/// database, fetching newest-first pages with `fetch(limit, offset)` until
+/// enough entries survive, a page reaches back before `since`, or the
+/// history runs out.
+pub(super) fn read_paged(
+    n: usize,
+    filter: &HistoryFilter,
+    mut fetch: impl FnMut(usize, usize) -> Vec<HistoryEntry>,
+) -> Vec<HistoryEntry> {
+    let page = n.max(100);
+    let mut newest_first: Vec<HistoryEntry> = Vec::new();
+    loop {
+        let rows = fetch(page, newest_first.len());
+        let exhausted = rows.len() < page || filter.reaches_since(&rows);
+        newest_first.extend(rows);
+        let entries = filter.apply(newest_first.iter().rev().cloned().collect());
+        if exhausted || entries.len() >= n {
+            return entries.into_iter().rev().take(n).rev().collect();
+        }
+    }
+}
+
+/// The commands to save for the selected `entries`. Where the recorded
+/// working directory changed between two of them without a `cd` being
+/// selected, the `cd` that happened is added.
+pub fn stack_commands(entries: &[HistoryEntry]) -> Vec<String> {
+    let mut commands = Vec::with_capacity(entries.len());
+    let mut previous: Option<&HistoryEntry> = None;
+    for entry in entries {
+        if let (Some(prev), Some(cwd)) = (previous, &entry.cwd) {
+            let changes_dir = ["cd", "pushd", "popd"]
+                .contains(&prev.command.split_whitespace().next().unwrap_or_default());
+            if prev.cwd.as_ref().is_some_and(|p| p != cwd) && !changes_dir {
+                commands.push(format!("cd {}", crate::journal::shell_quote(cwd)));
+            }
+        }
+        commands.push(entry.command.clone());
+        previous = Some(entry);
+    }
+    commands
+}
+
+fn expand_path(path: &str) -> PathBuf {
+    PathBuf::from(shellexpand::tilde(path).into_owned())
+}
+
+/// Split raw history bytes into lines, replacing undecodable sequences
+/// instead of giving up on the rest of the file.
+pub(super) fn decode_lines(bytes: &[u8], zsh: bool) -> Vec<String> {
+    if bytes.is_empty() {
+        return vec![];
+    }
+    let bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+    bytes
+        .split(|&b| b == b'\n')
+        .map(|line| {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if zsh {
+                String::from_utf8_lossy(&zsh::unmetafy(line)).into_owned()
+            } else {
+                String::from_utf8_lossy(line).into_owned()
+            }
+        })
+        .collect()
+}
+
+pub(super) fn plain_entry(command: &str) -> HistoryEntry {
+    HistoryEntry {
+        command: command.to_string(),
+        ..Default::default()
+    }
+}
+
+/// Split test input into the lines a parser is given.
+#[cfg(test)]
+fn lines(text: &str) -> Vec<String> {
+    text.lines().map(String::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn read_tail_drops_the_entry_a_chunk_starts_in() {
+        // 37-byte, three-line bash entries, so the first chunk starts part of
+        // the way through one and holds about 1770 of them.
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        for i in 0..10_000 {
+            writeln!(file, "#{}\ncat <<EOF\nline {}\nEOF", 1_700_000_000 + i, 1000 + i).unwrap();
+        }
+        let history = BashHistory { path: file.path().to_path_buf() };
+        let filter = HistoryFilter::default();
+        for n in (1750..1800).chain([5000]) {
+            let entries = history.read(n, &filter);
+            assert_eq!(entries.len(), n);
+            for (entry, i) in entries.iter().zip(10_000 - n..) {
+                assert_eq!(entry.command, format!("cat <<EOF\nline {}\nEOF", 1000 + i));
+                assert_eq!(entry.timestamp, Some(1_700_000_000 + i as i64));
+            }
+        }
+    }
+}
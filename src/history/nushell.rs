@@ -0,0 +1,103 @@
+use super::{plain_entry, read_paged, read_tail, HistoryFilter, HistorySource, TextFormat};
+use crate::types::HistoryEntry;
+use rusqlite::{Connection, OpenFlags};
+use std::path::{Path, PathBuf};
+
+/// nushell's history: reedline's plaintext `history.txt`, or
+/// `history.sqlite3` when `$env.config.history.file_format = "sqlite"`.
+pub struct NushellHistory {
+    pub path: PathBuf,
+}
+
+impl NushellHistory {
+    /// The SQLite history if it exists, otherwise the plaintext one.
+    pub fn default_path() -> PathBuf {
+        let dir = dirs::config_dir().unwrap_or_default().join("nushell");
+        let db = dir.join("history.sqlite3");
+        if db.exists() {
+            db
+        } else {
+            dir.join("history.txt")
+        }
+    }
+}
+
+const FORMAT: TextFormat = TextFormat {
+    metafied: false,
+    parse: parse_nushell,
+    first_entry,
+};
+
+impl HistorySource for NushellHistory {
+    fn read(&self, n: usize, filter: &HistoryFilter) -> Vec<HistoryEntry> {
+        if self.path.extension().is_some_and(|ext| ext == "sqlite3" || ext == "db") {
+            return read_nushell_db(&self.path, n, filter);
+        }
+        read_tail(&self.path, n, &FORMAT, filter)
+    }
+}
+
+/// Read up to `n` recent entries (newest last) from nushell's SQLite history.
+fn read_nushell_db(path: &Path, n: usize, filter: &HistoryFilter) -> Vec<HistoryEntry> {
+    let conn = match Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY) {
+        Ok(conn) => conn,
+        Err(_) => return vec![],
+    };
+    let query = "SELECT command_line, start_timestamp, duration_ms, exit_status, cwd, session_id, hostname
+                 FROM history ORDER BY id DESC LIMIT ?1 OFFSET ?2";
+    let mut stmt = match conn.prepare(query) {
+        Ok(stmt) => stmt,
+        Err(_) => return vec![],
+    };
+    read_paged(n, filter, |limit, offset| {
+        let rows = stmt.query_map([limit as i64, offset as i64], |row| {
+            // Timestamps and durations are in milliseconds.
+            let timestamp: Option<i64> = row.get(1)?;
+            let duration: Option<i64> = row.get(2)?;
+            let session: Option<i64> = row.get(5)?;
+            Ok(HistoryEntry {
+                command: row.get(0)?,
+                timestamp: timestamp.map(|t| t / 1000),
+                duration: duration.map(|d| d / 1000),
+                exit_code: row.get::<_, Option<i64>>(3)?.map(|e| e as i32),
+                cwd: row.get(4)?,
+                session: session.map(|s| s.to_string()),
+                hostname: row.get(6)?,
+                ..Default::default()
+            })
+        });
+        match rows {
+            Ok(rows) => rows.filter_map(Result::ok).collect(),
+            Err(_) => vec![],
+        }
+    })
+}
+
+/// Parse nushell's plaintext `history.txt`, one entry per line with
+/// embedded newlines written as `<\n>`.
+fn parse_nushell(lines: Vec<String>) -> Vec<HistoryEntry> {
+    lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| plain_entry(&line.replace("<\\n>", "\n")))
+        .collect()
+}
+
+fn first_entry(_lines: &[String]) -> usize {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_escaped_newlines() {
+        let lines = ["echo a<\\n>echo b", "", "ls"].map(String::from).to_vec();
+        let entries = parse_nushell(lines);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].command, "echo a\necho b");
+        assert_eq!(entries[1].command, "ls");
+        assert_eq!(first_entry(&[]), 0);
+    }
+}
@@ -0,0 +1,71 @@
+use super::{plain_entry, read_tail, HistoryFilter, HistorySource, TextFormat};
+use crate::types::HistoryEntry;
+use std::path::PathBuf;
+
+/// PSReadLine's `ConsoleHost_history.txt`, as written by `pwsh`.
+pub struct PwshHistory {
+    pub path: PathBuf,
+}
+
+const FORMAT: TextFormat = TextFormat {
+    metafied: false,
+    parse: parse_powershell,
+    first_entry,
+};
+
+impl HistorySource for PwshHistory {
+    fn read(&self, n: usize, filter: &HistoryFilter) -> Vec<HistoryEntry> {
+        read_tail(&self.path, n, &FORMAT, filter)
+    }
+}
+
+/// Parse PSReadLine's `ConsoleHost_history.txt`, where every line of a
+/// multi-line entry except the last ends in a backtick.
+fn parse_powershell(lines: Vec<String>) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = Vec::new();
+    let mut continuing = false;
+    for line in lines {
+        let (text, continues) = match line.strip_suffix('`') {
+            Some(rest) => (rest, true),
+            None => (line.as_str(), false),
+        };
+        match entries.last_mut() {
+            Some(entry) if continuing => {
+                entry.command.push('\n');
+                entry.command.push_str(text);
+            }
+            _ => entries.push(plain_entry(text)),
+        }
+        continuing = continues;
+    }
+    entries.retain(|e| !e.command.trim().is_empty());
+    entries
+}
+
+/// PSReadLine continues an entry onto the next line after a trailing backtick.
+fn first_entry(lines: &[String]) -> usize {
+    lines
+        .iter()
+        .position(|l| !l.ends_with('`'))
+        .map_or(lines.len(), |i| i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::lines;
+    use super::*;
+
+    #[test]
+    fn joins_lines_ending_in_backticks() {
+        let entries = parse_powershell(lines("Get-ChildItem `\n  -Recurse `\n  -Force\nls\n"));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].command, "Get-ChildItem \n  -Recurse \n  -Force");
+        assert_eq!(entries[1].command, "ls");
+    }
+
+    #[test]
+    fn first_entry_skips_the_rest_of_a_continued_entry() {
+        assert_eq!(first_entry(&lines("  -Recurse `\n  -Force\nls")), 2);
+        assert_eq!(first_entry(&lines("ls\npwd")), 1);
+    }
+}
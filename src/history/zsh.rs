@@ -0,0 +1,143 @@
+use super::{read_tail, HistoryFilter, HistorySource, TextFormat};
+use crate::types::HistoryEntry;
+use std::path::PathBuf;
+
+/// zsh's `$HISTFILE`, in either the plain or `EXTENDED_HISTORY` format.
+pub struct ZshHistory {
+    pub path: PathBuf,
+}
+
+const FORMAT: TextFormat = TextFormat {
+    metafied: true,
+    parse: parse_zsh,
+    first_entry,
+};
+
+impl HistorySource for ZshHistory {
+    fn read(&self, n: usize, filter: &HistoryFilter) -> Vec<HistoryEntry> {
+        read_tail(&self.path, n, &FORMAT, filter)
+    }
+}
+
+/// Parse zsh history, with or without `EXTENDED_HISTORY`.
+/// Extended entries look like `: <start>:<elapsed>;<command>`, and a line
+/// ending in a backslash continues onto the next line.
+fn parse_zsh(lines: Vec<String>) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = Vec::new();
+    let mut continuing = false;
+    for line in lines {
+        if continuing {
+            if let Some(entry) = entries.last_mut() {
+                entry.command.push('\n');
+                continuing = push_zsh_line(&mut entry.command, &line);
+                continue;
+            }
+        }
+        let (mut entry, command) = match parse_zsh_extended(&line) {
+            Some((timestamp, duration, command)) => (
+                HistoryEntry {
+                    timestamp: Some(timestamp),
+                    duration: Some(duration),
+                    ..Default::default()
+                },
+                command,
+            ),
+            None => (HistoryEntry::default(), line.as_str()),
+        };
+        continuing = push_zsh_line(&mut entry.command, command);
+        entries.push(entry);
+    }
+    entries.retain(|e| !e.command.trim().is_empty());
+    entries
+}
+
+/// Append one physical line to `command`, returning whether the entry
+/// continues on the next line (zsh escapes embedded newlines with `\`).
+fn push_zsh_line(command: &mut String, line: &str) -> bool {
+    match line.strip_suffix('\\') {
+        Some(rest) => {
+            command.push_str(rest);
+            true
+        }
+        None => {
+            command.push_str(line);
+            false
+        }
+    }
+}
+
+/// Split an `EXTENDED_HISTORY` line into (start, elapsed, command).
+fn parse_zsh_extended(line: &str) -> Option<(i64, i64, &str)> {
+    let rest = line.strip_prefix(": ")?;
+    let (meta, command) = rest.split_once(';')?;
+    let (start, elapsed) = meta.split_once(':')?;
+    Some((start.trim().parse().ok()?, elapsed.trim().parse().ok()?, command))
+}
+
+/// zsh's metafication marker: the byte following it is XORed with 0x20.
+const ZSH_META: u8 = 0x83;
+
+/// Reverse zsh's metafication of history bytes.
+pub(super) fn unmetafy(line: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(line.len());
+    let mut bytes = line.iter();
+    while let Some(&b) = bytes.next() {
+        if b == ZSH_META {
+            if let Some(&next) = bytes.next() {
+                out.push(next ^ 0x20);
+            }
+        } else {
+            out.push(b);
+        }
+    }
+    out
+}
+
+/// zsh continues an entry onto the next line after a trailing backslash.
+fn first_entry(lines: &[String]) -> usize {
+    lines
+        .iter()
+        .position(|l| !l.ends_with('\\'))
+        .map_or(lines.len(), |i| i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{decode_lines, lines};
+    use super::*;
+
+    #[test]
+    fn parses_extended_and_plain_lines() {
+        let entries = parse_zsh(lines(": 1700000000:5;make build\nls -la\n"));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].command, "make build");
+        assert_eq!(entries[0].timestamp, Some(1700000000));
+        assert_eq!(entries[0].duration, Some(5));
+        assert_eq!(entries[1].command, "ls -la");
+        assert_eq!(entries[1].timestamp, None);
+    }
+
+    #[test]
+    fn joins_continued_lines() {
+        let text = ": 1700000000:0;for f in *; do\\\n  echo $f\\\ndone\n: 1700000001:0;ls\n";
+        let entries = parse_zsh(lines(text));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].command, "for f in *; do\n  echo $f\ndone");
+        assert_eq!(entries[1].command, "ls");
+    }
+
+    #[test]
+    fn unmetafies_lines() {
+        // "à" is C3 A0 in UTF-8, and zsh writes A0 as 83 80.
+        let bytes = b": 1700000000:0;echo \xc3\x83\x80\n";
+        assert_eq!(unmetafy(b"\xc3\x83\x80"), "à".as_bytes());
+        let entries = parse_zsh(decode_lines(bytes, true));
+        assert_eq!(entries[0].command, "echo à");
+    }
+
+    #[test]
+    fn first_entry_skips_the_rest_of_a_continued_entry() {
+        assert_eq!(first_entry(&lines("  echo $f\\\ndone\n: 1700000001:0;ls")), 2);
+        assert_eq!(first_entry(&lines("  echo $f\\\n  echo $g\\")), 2);
+    }
+}
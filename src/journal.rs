@@ -0,0 +1,172 @@
+//! comboman's own history journal, written by the shell hooks printed by
+//! `comboman init <shell>`. Each line records one command as tab-separated
+//! fields: start, duration, exit status, session, cwd and command, with
+//! backslashes, tabs and newlines escaped.
+
+use crate::types::HistoryEntry;
+use std::fs::{create_dir_all, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Location of the journal, honoring `COMBOMAN_JOURNAL`.
+pub fn journal_path() -> PathBuf {
+    if let Ok(path) = std::env::var("COMBOMAN_JOURNAL") {
+        return PathBuf::from(shellexpand::tilde(&path).into_owned());
+    }
+    let data_dir = dirs::data_dir().expect("Cannot find data directory");
+    data_dir.join("comboman").join("journal.tsv")
+}
+
+/// Append one command to the journal.
+pub fn append_record(entry: &HistoryEntry) {
+    let path = journal_path();
+    if let Some(dir) = path.parent() {
+        create_dir_all(dir).expect("Cannot create journal directory");
+    }
+    let line = format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\n",
+        entry.timestamp.unwrap_or_default(),
+        entry.duration.unwrap_or_default(),
+        entry.exit_code.unwrap_or_default(),
+        escape(entry.session.as_deref().unwrap_or_default()),
+        escape(entry.cwd.as_deref().unwrap_or_default()),
+        escape(&entry.command),
+    );
+    let mut f = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .expect("Cannot open journal");
+    // A single write keeps records from concurrent shells whole.
+    f.write_all(line.as_bytes()).expect("Cannot write journal");
+}
+
+/// Parse one journal line.
+pub fn parse_record(line: &str) -> Option<HistoryEntry> {
+    let mut fields = line.splitn(6, '\t');
+    let timestamp = fields.next()?.parse().ok()?;
+    let duration = fields.next()?.parse().ok()?;
+    let exit_code = fields.next()?.parse().ok()?;
+    let session = unescape(fields.next()?);
+    let cwd = unescape(fields.next()?);
+    let command = unescape(fields.next()?);
+    Some(HistoryEntry {
+        command,
+        timestamp: Some(timestamp),
+        duration: Some(duration),
+        exit_code: Some(exit_code),
+        session: (!session.is_empty()).then_some(session),
+        cwd: (!cwd.is_empty()).then_some(cwd),
+        ..Default::default()
+    })
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Quote `s` for use as a single shell word.
+pub fn shell_quote(s: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "/._-+:,@%=".contains(c);
+    if !s.is_empty() && s.chars().all(safe) {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+/// The hook script for `shell` (bash, zsh or fish), which calls `exe` to
+/// record every command run at the prompt.
+pub fn init_script(shell: &str, exe: &str) -> Option<String> {
+    let template = match shell {
+        "bash" => BASH_HOOK,
+        "zsh" => ZSH_HOOK,
+        "fish" => FISH_HOOK,
+        _ => return None,
+    };
+    Some(template.replace("__COMBOMAN__", &shell_quote(exe)))
+}
+
+const BASH_HOOK: &str = r#"# comboman journal hook. Add to ~/.bashrc:  eval "$(comboman init bash)"
+# Uses the DEBUG trap, replacing any existing one.
+COMBOMAN_SESSION="$$-$(date +%s)"
+export COMBOMAN_SESSION
+_comboman_ready=
+_comboman_start=
+_comboman_last=
+_comboman_preexec() {
+    [[ $BASH_COMMAND == _comboman_precmd ]] && return 0
+    [[ -n $_comboman_ready ]] || return 0
+    _comboman_ready=
+    _comboman_start=${EPOCHSECONDS:-$(date +%s)}
+    _comboman_cwd=$PWD
+}
+_comboman_precmd() {
+    local exit_code=$? now entry re='^ *([0-9]+)[* ] (.*)$'
+    _comboman_ready=
+    [[ -n $_comboman_start ]] || return 0
+    now=${EPOCHSECONDS:-$(date +%s)}
+    entry=$(HISTTIMEFORMAT= builtin history 1)
+    if [[ $entry =~ $re && ${BASH_REMATCH[1]} != "$_comboman_last" ]]; then
+        _comboman_last=${BASH_REMATCH[1]}
+        __COMBOMAN__ record --start "$_comboman_start" --duration "$((now - _comboman_start))" \
+            --exit "$exit_code" --session "$COMBOMAN_SESSION" --cwd "$_comboman_cwd" -- "${BASH_REMATCH[2]}"
+    fi
+    _comboman_start=
+}
+trap '_comboman_preexec' DEBUG
+PROMPT_COMMAND="_comboman_precmd${PROMPT_COMMAND:+;$PROMPT_COMMAND};_comboman_ready=1"
+"#;
+
+const ZSH_HOOK: &str = r#"# comboman journal hook. Add to ~/.zshrc:  eval "$(comboman init zsh)"
+zmodload zsh/datetime
+typeset -gx COMBOMAN_SESSION="$$-$EPOCHSECONDS"
+_comboman_preexec() {
+    typeset -g _comboman_cmd=$1 _comboman_cwd=$PWD _comboman_start=$EPOCHSECONDS
+}
+_comboman_precmd() {
+    local exit_code=$?
+    [[ -n $_comboman_start ]] || return 0
+    __COMBOMAN__ record --start "$_comboman_start" --duration "$((EPOCHSECONDS - _comboman_start))" \
+        --exit "$exit_code" --session "$COMBOMAN_SESSION" --cwd "$_comboman_cwd" -- "$_comboman_cmd"
+    unset _comboman_start
+}
+autoload -Uz add-zsh-hook
+add-zsh-hook preexec _comboman_preexec
+add-zsh-hook precmd _comboman_precmd
+"#;
+
+const FISH_HOOK: &str = r#"# comboman journal hook. Add to ~/.config/fish/config.fish:  comboman init fish | source
+set -gx COMBOMAN_SESSION $fish_pid-(date +%s)
+function _comboman_preexec --on-event fish_preexec
+    set -g _comboman_cwd $PWD
+    set -g _comboman_start (date +%s)
+end
+function _comboman_postexec --on-event fish_postexec
+    set -l exit_code $status
+    set -q _comboman_start; or return 0
+    __COMBOMAN__ record --start $_comboman_start --duration (math --scale=0 $CMD_DURATION / 1000) \
+        --exit $exit_code --session $COMBOMAN_SESSION --cwd $_comboman_cwd -- $argv[1]
+    set -e _comboman_start
+end
+"#;
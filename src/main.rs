@@ -3,15 +3,35 @@ mod store;
 mod ui;
 mod exec;
 mod history;
+mod config;
+mod journal;
+mod suggest;
+mod redact;
 
 use std::env;
 use std::io::{stdout, Write};
 
 use crate::exec::{edit_stack, run_combo};
-use crate::history::import_history;
-use crate::store::{add_combo, load_combos, save_combos, update_last_used};
-use crate::ui::{prompt_input, run_ui, select_save_option, select_stack};
-use crate::types::{Combo, SaveOption};
+use crate::config::load_config;
+use crate::history::{
+    default_source, import_history, parse_source_spec, parse_time, source_for, stack_commands,
+    HistoryFilter, Shell,
+};
+use crate::journal::{append_record, init_script};
+use crate::redact::{find_secrets, redact_all};
+use crate::store::{
+    add_combo, bundle_text, combo_location, convert_layout, find_combo, find_project_library,
+    last_change, list_backups, list_trash, load_all_combos, load_combos, modify_combos,
+    project_name, read_combo_file, record_use, restore_backup, restore_from_trash, set_commands,
+    sync, tagged_combos, trash_combo, undo_last_change, unique_name, Layout, Location,
+    StoreError,
+};
+use crate::suggest::suggest;
+use crate::ui::{
+    diff_text, prompt_input, resolve_conflict, resolve_merge, run_ui, select_redaction,
+    select_save_option, select_stack, select_suggestion, usage_summary,
+};
+use crate::types::{Combo, Conflict, HistoryEntry, SaveOption};
 use crossterm::{
     cursor::MoveTo,
     execute,
@@ -19,29 +39,638 @@ use crossterm::{
 };
 use std::fs;
 use std::path::Path;
+use std::time::Instant;
+use chrono::{Local, TimeZone};
 
 fn list_combos(combos: &[Combo]) {
     let mut sorted = combos.to_vec();
-    sorted.sort_by(|a, b| b.last_used.cmp(&a.last_used));
+    sorted.sort_by_key(|c| (c.origin.is_none(), std::cmp::Reverse(c.last_used)));
     for c in sorted {
-        println!("{} (last used: {})", c.name, c.last_used);
+        let mut line = c.name.clone();
+        if let Some(project) = c.origin.as_deref().map(project_name) {
+            line.push_str(&format!(" [{project}]"));
+        }
+        for tag in &c.tags {
+            line.push_str(&format!(" #{tag}"));
+        }
+        if let Some(description) = &c.description {
+            line.push_str(&format!(" - {description}"));
+        }
+        println!("{line}");
+        println!("    {}", usage_summary(&c));
     }
 }
 
-fn delete_combo(combos: &mut Vec<Combo>, name: &str, combo_directory: Option<String>) {
-    let before = combos.len();
-    combos.retain(|c| c.name != name);
-    if combos.len() < before {
-        save_combos(combos, combo_directory);
-        println!("Deleted combo '{name}'");
+/// Split user input such as `build, ci #deploy` into tags.
+fn parse_tags(input: &str) -> Vec<String> {
+    input
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .map(|t| t.trim_start_matches('#'))
+        .filter(|t| !t.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Change the metadata of the combo `name` as given by `args`, or with no
+/// changes given, edit its commands in $EDITOR.
+fn edit_combo(combos: &[Combo], name: &str, args: EditArgs, global: &Location) {
+    let Some(combo) = combos.iter().find(|c| c.name == name) else {
+        eprintln!("Combo '{name}' not found");
+        return;
+    };
+    let metadata_given = args.description.is_some()
+        || args.tags.is_some()
+        || args.notes.is_some()
+        || args.rename.is_some();
+    let commands = if metadata_given {
+        None
     } else {
+        let edited = edit_stack(combo.commands.clone());
+        if edited == combo.commands {
+            println!("No changes.");
+            return;
+        }
+        Some(edited)
+    };
+    let description = match &args.rename {
+        Some(new_name) => format!("rename '{name}' to '{new_name}'"),
+        None => format!("edit '{name}'"),
+    };
+    let result = modify_combos(&combo_location(combo, global), &description, |combos| {
+        if let Some(new_name) = &args.rename {
+            if combos.iter().any(|c| &c.name == new_name) {
+                return Err(format!("a combo named '{new_name}' already exists"));
+            }
+        }
+        let Some(c) = combos.iter_mut().find(|c| c.name == name) else {
+            return Err("it no longer exists".to_string());
+        };
+        if let Some(commands) = commands {
+            set_commands(c, commands);
+        }
+        if let Some(description) = args.description {
+            c.description = Some(description).filter(|d| !d.is_empty());
+        }
+        if let Some(tags) = args.tags {
+            c.tags = parse_tags(&tags.join(","));
+        }
+        if let Some(notes) = args.notes {
+            c.notes = Some(notes).filter(|n| !n.is_empty());
+        }
+        if let Some(new_name) = args.rename {
+            c.name = new_name;
+        }
+        Ok(())
+    });
+    match result {
+        Ok(Ok(())) => println!("Updated combo '{name}'"),
+        Ok(Err(e)) => eprintln!("Combo '{name}' not updated: {e}"),
+        Err(e) => eprintln!("Combo '{name}' not updated: {e}"),
+    }
+}
+
+/// Print each version of the combo `name`, oldest first, with the lines
+/// that changed from the version before.
+fn show_history(combos: &[Combo], name: &str) {
+    let Some(combo) = combos.iter().find(|c| c.name == name) else {
+        eprintln!("Combo '{name}' not found");
+        return;
+    };
+    let format_time = |t: i64| {
+        Local
+            .timestamp_opt(t, 0)
+            .single()
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default()
+    };
+    let versions: Vec<&[String]> = combo
+        .revisions
+        .iter()
+        .map(|r| r.commands.as_slice())
+        .chain([combo.commands.as_slice()])
+        .collect();
+    for (i, commands) in versions.iter().enumerate() {
+        // Version i+1 was written when version i was replaced.
+        let mut header = format!("v{}", i + 1);
+        match i.checked_sub(1).map(|p| &combo.revisions[p]) {
+            Some(previous) => {
+                header.push_str(&format!("  {}", format_time(previous.changed_at)));
+                if let Some(author) = &previous.author {
+                    header.push_str(&format!("  by {author}"));
+                }
+            }
+            None => {
+                if let Some(created) = combo.created_at {
+                    header.push_str(&format!("  {}", format_time(created)));
+                }
+            }
+        }
+        if i + 1 == versions.len() {
+            header.push_str("  (current)");
+        }
+        println!("{header}");
+        match i.checked_sub(1) {
+            Some(p) => diff_text(versions[p], commands).iter().for_each(|l| println!("  {l}")),
+            None => commands.iter().for_each(|c| println!("    {c}")),
+        }
+    }
+}
+
+/// Make the commands of version `version` of the combo `name`, as numbered
+/// by `history`, its current ones again.
+fn revert_combo(combos: &[Combo], name: &str, version: usize, global: &Location) {
+    let Some(combo) = combos.iter().find(|c| c.name == name) else {
         eprintln!("Combo '{name}' not found");
+        return;
+    };
+    let description = format!("revert '{name}' to v{version}");
+    let result = modify_combos(&combo_location(combo, global), &description, |combos| {
+        let Some(c) = combos.iter_mut().find(|c| c.name == name) else {
+            return Err("it no longer exists".to_string());
+        };
+        let current = c.revisions.len() + 1;
+        if version == current {
+            return Err(format!("v{version} is the current version"));
+        }
+        let Some(revision) = version.checked_sub(1).and_then(|i| c.revisions.get(i)) else {
+            return Err(format!("there is no v{version}; versions run from v1 to v{current}"));
+        };
+        set_commands(c, revision.commands.clone());
+        Ok(())
+    });
+    match result {
+        Ok(Ok(())) => println!("Reverted combo '{name}' to v{version}"),
+        Ok(Err(e)) => eprintln!("Combo '{name}' not reverted: {e}"),
+        Err(e) => eprintln!("Combo '{name}' not reverted: {e}"),
+    }
+}
+
+fn delete_combo(combos: &[Combo], name: &str, global: &Location) {
+    let Some(combo) = combos.iter().find(|c| c.name == name) else {
+        eprintln!("Combo '{name}' not found");
+        return;
+    };
+    let location = combo_location(combo, global);
+    let deleted = trash_combo(&location, name);
+    // Purge combos past their retention period.
+    list_trash(&location, load_config().trash.retention_days);
+    match deleted {
+        Ok(true) => println!("Deleted combo '{name}'; 'comboman undo' brings it back."),
+        Ok(false) => eprintln!("Combo '{name}' not found"),
+        Err(e) => eprintln!("Combo '{name}' not deleted: {e}"),
+    }
+}
+
+/// The libraries in use, the nearest project library first.
+fn libraries(global: &Location) -> Vec<Location> {
+    let cwd = env::current_dir().expect("Cannot read current directory");
+    let mut libraries: Vec<Location> =
+        find_project_library(&cwd).map(Location::Project).into_iter().collect();
+    libraries.push(global.clone());
+    libraries
+}
+
+/// List the trash of each library, or restore a combo from it.
+fn manage_trash(command: TrashCommands, global: &Location) {
+    let retention_days = load_config().trash.retention_days;
+    match command {
+        TrashCommands::List => {
+            let mut empty = true;
+            for location in libraries(global) {
+                let project = match &location {
+                    Location::Project(path) => Some(project_name(path)),
+                    Location::Global(_) => None,
+                };
+                for trashed in list_trash(&location, retention_days).iter().rev() {
+                    empty = false;
+                    let deleted = Local
+                        .timestamp_opt(trashed.deleted_at, 0)
+                        .single()
+                        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                        .unwrap_or_default();
+                    match &project {
+                        Some(project) => {
+                            println!("{} [{project}] (deleted {deleted})", trashed.combo.name)
+                        }
+                        None => println!("{} (deleted {deleted})", trashed.combo.name),
+                    }
+                }
+            }
+            if empty {
+                println!("The trash is empty.");
+            }
+        }
+        TrashCommands::Restore { name } => {
+            for location in libraries(global) {
+                match restore_from_trash(&location, &name) {
+                    Ok(Ok(true)) => {
+                        println!("Restored combo '{name}'");
+                        return;
+                    }
+                    Ok(Ok(false)) => {}
+                    Ok(Err(e)) => {
+                        eprintln!("Combo '{name}' not restored: {e}");
+                        return;
+                    }
+                    Err(e) => {
+                        eprintln!("Combo '{name}' not restored: {e}");
+                        return;
+                    }
+                }
+            }
+            eprintln!("Combo '{name}' is not in the trash");
+        }
+    }
+}
+
+/// Undo the most recent change to the project or global library.
+fn undo(global: &Location) {
+    let latest = libraries(global)
+        .into_iter()
+        .filter_map(|location| last_change(&location).map(|(_, at)| (at, location)))
+        .max_by_key(|(at, _)| *at);
+    let Some((_, location)) = latest else {
+        println!("Nothing to undo.");
+        return;
+    };
+    match undo_last_change(&location) {
+        Ok(Some(description)) => println!("Undid: {description}"),
+        Ok(None) => println!("Nothing to undo."),
+        Err(e) => eprintln!("Cannot undo: {e}"),
+    }
+}
+
+/// Write the combos called `names`, or all combos, as a bundle to `output`
+/// or stdout.
+fn export_combos(combos: &[Combo], names: &[String], output: Option<String>) {
+    let selected: Vec<Combo> = if names.is_empty() {
+        combos.to_vec()
+    } else {
+        let mut selected = Vec::new();
+        for name in names {
+            match combos.iter().find(|c| &c.name == name) {
+                Some(c) => selected.push(c.clone()),
+                None => {
+                    eprintln!("Combo '{name}' not found");
+                    return;
+                }
+            }
+        }
+        selected
+    };
+    let bundle = bundle_text(&selected);
+    match output {
+        Some(path) => match fs::write(&path, bundle) {
+            Ok(()) => println!("Exported {} combos to {path}", selected.len()),
+            Err(e) => eprintln!("Cannot write {path}: {e}"),
+        },
+        None => print!("{bundle}"),
+    }
+}
+
+/// Add the combos in the bundle at `path` to the library at `location`,
+/// asking what to do with each one whose name is already taken.
+fn import_combos(path: &str, location: &Location) {
+    let incoming = match read_combo_file(Path::new(path)) {
+        Ok(combos) => combos,
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+    let existing = match load_combos(location) {
+        Ok(combos) => combos,
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+    // Combos to add, paired with whether they replace one of the same name.
+    let mut plan: Vec<(Combo, bool)> = Vec::new();
+    let mut taken = existing.clone();
+    let mut skipped = 0;
+    for mut combo in incoming {
+        combo.origin = None;
+        match existing.iter().find(|c| c.name == combo.name) {
+            None => {}
+            Some(current) if current.commands == combo.commands => {
+                println!("'{}' is already present.", combo.name);
+                skipped += 1;
+                continue;
+            }
+            Some(current) => {
+                let renamed = unique_name(&taken, &combo.name);
+                match resolve_conflict(current, &combo, &renamed) {
+                    Some(Conflict::Skip) => {
+                        skipped += 1;
+                        continue;
+                    }
+                    Some(Conflict::Rename) => combo.name = renamed,
+                    Some(Conflict::Overwrite) => {
+                        plan.push((combo, true));
+                        continue;
+                    }
+                    None => {
+                        println!("Import cancelled.");
+                        return;
+                    }
+                }
+            }
+        }
+        taken.push(combo.clone());
+        plan.push((combo, false));
+    }
+    let imported = plan.len();
+    let result = modify_combos(location, &format!("import {path}"), |combos| {
+        for (mut combo, replace) in plan {
+            match combos.iter_mut().find(|c| c.name == combo.name) {
+                Some(current) if replace => {
+                    combo.last_used = current.last_used;
+                    combo.use_count = current.use_count;
+                    combo.total_runtime = current.total_runtime;
+                    let commands = std::mem::take(&mut combo.commands);
+                    combo.commands = std::mem::take(&mut current.commands);
+                    combo.revisions = std::mem::take(&mut current.revisions);
+                    *current = combo;
+                    set_commands(current, commands);
+                }
+                // Added by someone else since the conflicts were resolved.
+                Some(_) => {
+                    combo.name = unique_name(combos, &combo.name);
+                    combos.push(combo);
+                }
+                None => combos.push(combo),
+            }
+        }
+    });
+    match result {
+        Ok(()) => println!("Imported {imported} combos, skipped {skipped}."),
+        Err(e) => eprintln!("Nothing imported: {e}"),
+    }
+}
+
+/// List the backups of the combo file, or restore one of them.
+fn restore_combos(backup: Option<String>, list: bool, location: &Location) {
+    if list {
+        let backups = list_backups(location);
+        if backups.is_empty() {
+            println!("No backups.");
+        }
+        for path in backups.iter().rev() {
+            let count = read_combo_file(path)
+                .ok()
+                .map_or("unreadable".to_string(), |c| format!("{} combos", c.len()));
+            println!("{} ({count})", path.file_name().unwrap_or_default().to_string_lossy());
+        }
+        return;
+    }
+    match restore_backup(location, backup.as_deref()) {
+        Ok((path, combos)) => println!(
+            "Restored {} combos from {}",
+            combos.len(),
+            path.file_name().unwrap_or_default().to_string_lossy()
+        ),
+        Err(e) => eprintln!("{e}"),
+    }
+}
+
+/// Before `stack` is written out in plaintext, warn about any secrets it
+/// still contains and ask whether to go ahead.
+fn confirm_secrets(stack: &[String]) -> bool {
+    let found = find_secrets(stack).len();
+    if found == 0 {
+        return true;
+    }
+    let answer = prompt_input(&format!(
+        "The stack contains {found} possible secret(s) that will be saved in plaintext. Save anyway? [y/N] "
+    ));
+    answer.as_deref() == Some("y")
+}
+
+/// Let the user edit `stack` and save it as a combo in the library at
+/// `location`, a script or a function.
+fn save_stack(mut stack: Vec<String>, combos: &[Combo], location: &Location) {
+    let findings = find_secrets(&stack);
+    redact_all(&mut stack, &findings, select_redaction);
+    loop {
+        match select_save_option(&stack) {
+            Some(SaveOption::Edit) => {
+                stack = edit_stack(stack);
+                execute!(stdout(), Clear(ClearType::All), MoveTo(0, 0)).unwrap();
+            }
+            Some(SaveOption::SaveAsScript) => {
+                if !confirm_secrets(&stack) {
+                    continue;
+                }
+                if let Some(path) = prompt_input("Enter path to save script: ") {
+                    let script = stack.join("\n");
+                    if fs::write(&path, script).is_ok() {
+                        println!("\nSaved script to {path}");
+                    } else {
+                        eprintln!("Failed to save script to {path}");
+                    }
+                }
+                break;
+            }
+            Some(SaveOption::SaveAsFunction) => {
+                if !confirm_secrets(&stack) {
+                    continue;
+                }
+                let name = prompt_input("Enter function name (leave blank for default): ");
+                let func_name = name.unwrap_or_else(|| format!("command_{}", combos.len() + 1));
+                let function = format!("\n{} () {{ \n{}\n }}\n", func_name, stack.join("\n"));
+
+                // Simplified: Append to .bashrc. A real implementation would need to be more robust.
+                let shell_rc = Path::new(&env::var("HOME").unwrap()).join(".bashrc");
+                if let Ok(mut file) = fs::OpenOptions::new().append(true).open(&shell_rc) {
+                    if file.write_all(function.as_bytes()).is_ok() {
+                        println!("\nAdded function '{func_name}' to your shell rc file.");
+                        println!("Run 'source {}' to use it.", shell_rc.display());
+                    } else {
+                        eprintln!("Failed to write to shell rc file.");
+                    }
+                } else {
+                    eprintln!("Could not open shell rc file.");
+                }
+                break;
+            }
+            Some(SaveOption::SaveAsCombo) => {
+                let name = prompt_input("Enter name for combo (leave blank to auto-generate): ");
+                let description = prompt_input("Enter a description (optional): ");
+                let tags = prompt_input("Enter tags, separated by commas or spaces (optional): ");
+                let combo = Combo {
+                    name: name.unwrap_or_default(),
+                    description,
+                    tags: parse_tags(tags.as_deref().unwrap_or_default()),
+                    commands: stack,
+                    ..Default::default()
+                };
+                match add_combo(combo, location) {
+                    Ok(name) => println!("\nCombo '{name}' saved."),
+                    Err(e) => eprintln!("\nCombo not saved: {e}"),
+                }
+                break;
+            }
+            None => {
+                println!("Selection cancelled.");
+                break;
+            }
+        }
+    }
+}
+
+use clap::{Args, Parser, Subcommand};
+
+#[derive(Args)]
+struct HistoryArgs {
+    /// Read history from this file instead of the shell's default
+    #[arg(long)]
+    history_file: Option<String>,
+    /// History format to read: bash, zsh, fish, nu, pwsh, atuin or journal
+    #[arg(long)]
+    shell: Option<String>,
+    /// Maximum number of recent commands to read [default: 200, or 5000 for suggest]
+    #[arg(long)]
+    limit: Option<usize>,
+    /// Extra history to merge in, as `<shell>:<path>` or a path (repeatable)
+    #[arg(long)]
+    source: Vec<String>,
+    /// Only show commands run since this long ago (e.g. 2h, 3d) or this date
+    #[arg(long)]
+    since: Option<String>,
+    /// Only show commands run before this long ago (e.g. 2h, 3d) or this date
+    #[arg(long)]
+    until: Option<String>,
+    /// Only show commands run in this directory or below it
+    #[arg(long)]
+    cwd: Option<String>,
+    /// Only show commands from this shell session, or `current` for this one
+    #[arg(long)]
+    session: Option<String>,
+}
+
+/// Read the history requested by `args`, or None after reporting a bad source.
+fn load_history(args: HistoryArgs, default_limit: usize) -> Option<Vec<HistoryEntry>> {
+    let history_file = args.history_file.map(|f| shellexpand::tilde(&f).into_owned().into());
+    let primary = match (args.shell, history_file) {
+        (Some(shell), path) => source_for(&shell, path),
+        (None, Some(path)) => Some(Shell::detect().source(path)),
+        (None, None) => Some(default_source()),
+    };
+    let Some(primary) = primary else {
+        eprintln!("Unknown shell; expected one of bash, zsh, fish, nu, pwsh, atuin or journal.");
+        return None;
+    };
+    let mut sources = vec![primary];
+    for spec in &args.source {
+        match parse_source_spec(spec) {
+            Some(s) => sources.push(s),
+            None => {
+                eprintln!("Cannot read history source '{spec}'.");
+                return None;
+            }
+        }
+    }
+    let mut filter = HistoryFilter::from_config(&load_config().history);
+    for (spec, bound) in [(&args.since, &mut filter.since), (&args.until, &mut filter.until)] {
+        if let Some(spec) = spec {
+            let Some(time) = parse_time(spec) else {
+                eprintln!("Cannot parse time '{spec}'; expected e.g. 30m, 2h, 1d or 2024-01-31 14:00.");
+                return None;
+            };
+            *bound = Some(time);
+        }
+    }
+    if let Some(dir) = &args.cwd {
+        let dir = shellexpand::tilde(dir).into_owned();
+        let dir = fs::canonicalize(&dir).map_or(dir, |d| d.to_string_lossy().into_owned());
+        filter.cwd = Some(dir);
+    }
+    match args.session.as_deref() {
+        Some("current") => {
+            filter.sessions = ["COMBOMAN_SESSION", "ATUIN_SESSION"]
+                .iter()
+                .filter_map(|var| env::var(var).ok())
+                .collect();
+            if filter.sessions.is_empty() {
+                eprintln!("No current session; load the hooks from 'comboman init' or use Atuin.");
+                return None;
+            }
+        }
+        Some(id) => filter.sessions = vec![id.to_string()],
+        None => {}
     }
+    Some(import_history(&sources, args.limit.unwrap_or(default_limit), &filter))
 }
 
+/// The library to save new combos to or restore: the nearest project
+/// library when `project` is set, otherwise the global one. None after
+/// reporting that there is no project library.
+fn target_library(project: bool, global: &Location) -> Option<Location> {
+    if !project {
+        return Some(global.clone());
+    }
+    let cwd = env::current_dir().expect("Cannot read current directory");
+    match find_project_library(&cwd) {
+        Some(library) => Some(Location::Project(library)),
+        None => {
+            eprintln!("No project library found; create a .comboman directory or .comboman.yaml file in the project.");
+            None
+        }
+    }
+}
 
+/// Run `load` on the global library and the nearest project library, if
+/// any. When the project library cannot be read, say so and carry on with
+/// the global one alone; exit when that cannot be read either.
+fn load_libraries<T>(load: impl Fn(Option<&Path>) -> Result<T, StoreError>) -> T {
+    let cwd = env::current_dir().expect("Cannot read current directory");
+    let project = find_project_library(&cwd);
+    let loaded = load(project.as_deref()).or_else(|e| match &project {
+        Some(project) if e.path().is_some_and(|p| p.starts_with(project)) => {
+            eprintln!("{e}");
+            eprintln!(
+                "Skipping the project's combos. Fix the file, or run 'comboman restore --project' to roll it back to a backup."
+            );
+            load(None)
+        }
+        _ => Err(e),
+    });
+    loaded.unwrap_or_else(|e| {
+        eprintln!("{e}");
+        eprintln!("Fix the file, or run 'comboman restore' to roll back to a backup.");
+        std::process::exit(1);
+    })
+}
+
+/// Idle time in seconds that separates sessions in the history selector.
+fn session_gap() -> i64 {
+    load_config().history.session_gap_minutes * 60
+}
 
-use clap::Parser;
+#[derive(Args)]
+struct EditArgs {
+    /// New description; an empty one removes it
+    #[arg(long)]
+    description: Option<String>,
+    /// Comma-separated tags, replacing the current ones
+    #[arg(long, value_delimiter = ',')]
+    tags: Option<Vec<String>>,
+    /// New notes; empty ones remove them
+    #[arg(long)]
+    notes: Option<String>,
+    /// New name for the combo
+    #[arg(long)]
+    rename: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum TrashCommands {
+    /// List deleted combos, most recent first
+    List,
+    /// Put a deleted combo back
+    Restore { name: String },
+}
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -57,102 +686,203 @@ struct Cli {
 enum Commands {
     List,
     Delete { name: String },
-    New,
+    /// List or restore deleted combos
+    Trash {
+        #[command(subcommand)]
+        command: TrashCommands,
+    },
+    /// Undo the last change to your combos: an add, edit, revert, delete, import or undo
+    Undo,
+    /// Write combos to a bundle file that can be shared and imported
+    Export {
+        /// Combos to export [default: all]
+        names: Vec<String>,
+        /// File to write the bundle to [default: stdout]
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Add the combos in a bundle file written by `export`
+    Import {
+        bundle: String,
+        /// Import into the nearest project library instead of the global one
+        #[arg(long)]
+        project: bool,
+    },
+    /// Change a combo's description, tags, notes or name, or edit its commands
+    Edit {
+        name: String,
+        #[command(flatten)]
+        changes: EditArgs,
+    },
+    /// Show each version of a combo's commands and what changed between them
+    History { name: String },
+    /// Restore a version of a combo's commands, as numbered by `history`
+    Revert { name: String, version: usize },
+    New {
+        #[command(flatten)]
+        history: HistoryArgs,
+        /// Save to the nearest project library instead of the global one
+        #[arg(long)]
+        project: bool,
+    },
+    /// Suggest combos from command sequences that recur in your history
+    Suggest {
+        #[command(flatten)]
+        history: HistoryArgs,
+        /// Save to the nearest project library instead of the global one
+        #[arg(long)]
+        project: bool,
+    },
     #[command(name = "run")]
     Run {
         name: Option<String>,
         #[arg(long)]
         no_confirm: bool,
     },
+    /// Roll the combo file back to a backup taken before an earlier change
+    Restore {
+        /// Backup to restore, as shown by --list [default: the newest]
+        backup: Option<String>,
+        /// List the available backups instead
+        #[arg(long)]
+        list: bool,
+        /// Restore the nearest project library instead of the global one
+        #[arg(long)]
+        project: bool,
+    },
+    /// Pull combos from the git remote of the combo directory and push yours
+    Sync,
+    /// Store combos in one `combos.yaml` ("file"), one file per combo ("directory")
+    /// or an SQLite database ("sqlite")
+    Convert { layout: String },
+    /// Print shell hooks that record each command to comboman's journal
+    Init { shell: String },
+    /// Append a command to the journal (used by the `init` hooks)
+    #[command(hide = true)]
+    Record {
+        #[arg(long)]
+        start: i64,
+        #[arg(long)]
+        duration: i64,
+        #[arg(long)]
+        exit: i32,
+        #[arg(long, default_value = "")]
+        session: String,
+        #[arg(long, default_value = "")]
+        cwd: String,
+        #[arg(allow_hyphen_values = true)]
+        command: String,
+    },
 }
 
 fn main() {
     let cli = Cli::parse();
-    let combo_dir = cli.combo_directory.clone();
+    let global = Location::Global(cli.combo_directory.clone());
 
-    let mut combos = load_combos(cli.combo_directory);
+    // The hooks run `record` at every prompt, so skip loading combos for it,
+    // `restore` has to work when the combo file cannot be loaded, and
+    // `run <name>` looks up only the combos it needs.
+    let combos = if matches!(
+        cli.command,
+        Commands::Init { .. }
+            | Commands::Record { .. }
+            | Commands::Restore { .. }
+            | Commands::Run { name: Some(_), .. }
+    ) {
+        vec![]
+    } else {
+        load_libraries(|project| load_all_combos(&global, project))
+    };
 
     match cli.command {
         Commands::List => list_combos(&combos),
         Commands::Delete { name } => {
-            delete_combo(&mut combos, &name, combo_dir);
+            delete_combo(&combos, &name, &global);
         }
-        Commands::New => {
+        Commands::Edit { name, changes } => edit_combo(&combos, &name, changes, &global),
+        Commands::History { name } => show_history(&combos, &name),
+        Commands::Revert { name, version } => revert_combo(&combos, &name, version, &global),
+        Commands::Trash { command } => manage_trash(command, &global),
+        Commands::Undo => undo(&global),
+        Commands::Export { names, output } => export_combos(&combos, &names, output),
+        Commands::Import { bundle, project } => {
+            if let Some(location) = target_library(project, &global) {
+                import_combos(&bundle, &location);
+            }
+        }
+        Commands::New { history, project } => {
+            let Some(location) = target_library(project, &global) else {
+                return;
+            };
             // Import recent history and open stack-like selector
-            let recent_cmds = import_history(200); // show up to 200 recent commands
+            let Some(recent_cmds) = load_history(history, 200) else {
+                return;
+            };
             if recent_cmds.is_empty() {
                 println!("No history found.");
                 return;
             }
-            let mut stack = match select_stack(recent_cmds, None) {
-                Some(s) if !s.is_empty() => s,
+            let stack = match select_stack(recent_cmds, None, session_gap()) {
+                Some(s) if !s.is_empty() => stack_commands(&s),
                 _ => {
                     println!("No commands selected or selection cancelled.");
                     return;
                 }
             };
-
-            loop {
-                match select_save_option(&stack) {
-                    Some(SaveOption::Edit) => {
-                        stack = edit_stack(stack);
-                        execute!(stdout(), Clear(ClearType::All), MoveTo(0, 0)).unwrap();
-                    }
-                    Some(SaveOption::SaveAsScript) => {
-                        if let Some(path) = prompt_input("Enter path to save script: ") {
-                            let script = stack.join("\n");
-                            if fs::write(&path, script).is_ok() {
-                                println!("\nSaved script to {path}");
-                            } else {
-                                eprintln!("Failed to save script to {path}");
-                            }
-                        }
-                        break;
-                    }
-                    Some(SaveOption::SaveAsFunction) => {
-                        let name = prompt_input("Enter function name (leave blank for default): ");
-                        let func_name =
-                            name.unwrap_or_else(|| format!("command_{}", combos.len() + 1));
-                        let function =
-                            format!("\n{} () {{ \n{}\n }}\n", func_name, stack.join("\n"));
-
-                        // Simplified: Append to .bashrc. A real implementation would need to be more robust.
-                        let shell_rc = Path::new(&env::var("HOME").unwrap()).join(".bashrc");
-                        if let Ok(mut file) = fs::OpenOptions::new().append(true).open(&shell_rc) {
-                            if file.write_all(function.as_bytes()).is_ok() {
-                                println!("\nAdded function '{func_name}' to your shell rc file.");
-                                println!("Run 'source {}' to use it.", shell_rc.display());
-                            } else {
-                                eprintln!("Failed to write to shell rc file.");
-                            }
-                        } else {
-                            eprintln!("Could not open shell rc file.");
-                        }
-                        break;
-                    }
-                    Some(SaveOption::SaveAsCombo) => {
-                        let name =
-                            prompt_input("Enter name for combo (leave blank to auto-generate): ");
-                        add_combo(&mut combos, stack, name, combo_dir.clone());
-                        println!("\nCombo saved.");
-                        break;
-                    }
-                    None => {
-                        println!("Selection cancelled.");
-                        break;
-                    }
-                }
+            save_stack(stack, &combos, &location);
+        }
+        Commands::Suggest { history, project } => {
+            let Some(location) = target_library(project, &global) else {
+                return;
+            };
+            let Some(history) = load_history(history, 5000) else {
+                return;
+            };
+            let suggestions = suggest(&history, 20);
+            if suggestions.is_empty() {
+                println!("No repeated command sequences found.");
+                return;
             }
+            let Some(choice) = select_suggestion(&suggestions, &history) else {
+                println!("Selection cancelled.");
+                return;
+            };
+            let initial: Vec<String> = suggestions[choice]
+                .latest
+                .iter()
+                .map(|&i| history[i].command.clone())
+                .collect();
+            let stack = match select_stack(history, Some(initial), session_gap()) {
+                Some(s) if !s.is_empty() => stack_commands(&s),
+                _ => {
+                    println!("No commands selected or selection cancelled.");
+                    return;
+                }
+            };
+            save_stack(stack, &combos, &location);
         }
         Commands::Run { name, no_confirm } => {
-            // Interactive run UI
+            // `run '#tag'` offers the combos with that tag in the menu.
+            let combos = match name.as_deref().map(|n| (n, n.strip_prefix('#'))) {
+                None => combos,
+                Some((_, Some(tag))) => {
+                    load_libraries(|project| tagged_combos(&global, project, tag))
+                }
+                Some((name, None)) => load_libraries(|project| {
+                    find_combo(&global, project, name).map(Vec::from_iter)
+                }),
+            };
             if combos.is_empty() {
-                println!("No saved combos. Use 'comboman new' to create one.");
+                match name {
+                    Some(tag) if tag.starts_with('#') => println!("No combos tagged {tag}."),
+                    Some(name) => eprintln!("No combo named '{name}'."),
+                    None => println!("No saved combos. Use 'comboman new' to create one."),
+                }
                 return;
             }
             let combo_name = match name {
-                Some(n) => n,
-                None => run_ui(combos.clone()).expect("No combo selected"),
+                Some(n) if !n.starts_with('#') => n,
+                _ => run_ui(combos.clone()).expect("No combo selected"),
             };
 
             if let Some(combo) = combos.iter().find(|c| c.name == combo_name) {
@@ -163,12 +893,65 @@ fn main() {
                         return;
                     }
                 }
+                let started = Instant::now();
                 run_combo(combo);
-                update_last_used(&mut combos, &combo_name);
-                save_combos(&combos, combo_dir.clone());
+                let runtime = started.elapsed();
+                let location = combo_location(combo, &global);
+                if let Err(e) = record_use(&location, &combo_name, runtime) {
+                    eprintln!("Cannot record use of '{combo_name}': {e}");
+                }
             } else {
                 eprintln!("Selected combo '{combo_name}' not found (concurrent modification?).");
             }
         }
+        Commands::Restore { backup, list, project } => {
+            if let Some(location) = target_library(project, &global) {
+                restore_combos(backup, list, &location);
+            }
+        }
+        Commands::Sync => match sync(&global, resolve_merge) {
+            Ok(()) => println!("Combos are in sync with the remote."),
+            Err(e) => eprintln!("{e}"),
+        },
+        Commands::Convert { layout } => {
+            let (to, stored) = match layout.as_str() {
+                "file" => (Layout::File, "in combos.yaml"),
+                "directory" | "dir" => (Layout::Directory, "one per file in combos/"),
+                "sqlite" => (Layout::Sqlite, "in the SQLite database combos.db"),
+                _ => {
+                    eprintln!("Unknown layout '{layout}'; expected file, directory or sqlite.");
+                    return;
+                }
+            };
+            match convert_layout(&global, to) {
+                Ok(()) => println!("Combos are now stored {stored}."),
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+        Commands::Init { shell } => {
+            let exe = env::current_exe().expect("Cannot locate comboman executable");
+            match init_script(&shell, &exe.to_string_lossy()) {
+                Some(script) => print!("{script}"),
+                None => eprintln!("Unsupported shell '{shell}'; expected bash, zsh or fish."),
+            }
+        }
+        Commands::Record {
+            start,
+            duration,
+            exit,
+            session,
+            cwd,
+            command,
+        } => {
+            append_record(&HistoryEntry {
+                command,
+                timestamp: Some(start),
+                duration: Some(duration),
+                exit_code: Some(exit),
+                session: Some(session),
+                cwd: Some(cwd),
+                ..Default::default()
+            });
+        }
     }
 }
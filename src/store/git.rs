@@ -0,0 +1,637 @@
+//! Keeping a combo directory that lives in a git repository committed and in
+//! sync with its remote.
+
+use super::sqlite::SqliteStorage;
+use super::yaml::FileStorage;
+use super::{
+    combo_file_paths, definition, encode_file_name, lock_store, read_document, store_paths,
+    write_atomic, write_error, ComboFile, Location, Storage, StoreError, CURRENT_VERSION,
+    GITIGNORE,
+};
+use crate::types::{Combo, Merge};
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use tempfile::NamedTempFile;
+
+/// Files of a combo directory that hold combos, in any layout. The rest,
+/// such as backups and usage data, stays out of git.
+const COMBO_FILES: &[&str] = &["combos.yaml", "combos", "combos.db", ".gitignore"];
+
+/// What a file in a combo directory holds.
+#[derive(Clone, Copy)]
+enum Kind {
+    /// A whole library, in the file layout.
+    File,
+    /// A whole library, in the SQLite layout.
+    Database,
+    /// One combo of the directory layout.
+    Combo,
+}
+
+/// Start git in `dir` and wait for it.
+fn git<S: Into<OsString>>(
+    dir: &Path,
+    args: impl IntoIterator<Item = S>,
+) -> std::io::Result<Output> {
+    Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args.into_iter().map(Into::into))
+        .output()
+}
+
+/// Run git in `dir`, returning its output, or its error message if it fails.
+fn run<S: Into<OsString>>(dir: &Path, args: impl IntoIterator<Item = S>) -> Result<String, String> {
+    let output = git(dir, args).map_err(|e| format!("Cannot run git: {e}"))?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Err(format!("{stdout}{stderr}").trim().to_string())
+    }
+}
+
+/// Whether `dir` is inside a git work tree.
+fn in_repository(dir: &Path) -> bool {
+    run(dir, ["rev-parse", "--is-inside-work-tree"]).is_ok_and(|out| out.trim() == "true")
+}
+
+/// The combo files in `dir` that git should track: those that exist and are
+/// not ignored, or that are tracked already and may have been removed.
+fn combo_files(dir: &Path) -> Vec<&'static str> {
+    COMBO_FILES
+        .iter()
+        .copied()
+        .filter(|name| {
+            if dir.join(name).exists() {
+                run(dir, ["check-ignore", "-q", name]).is_err()
+            } else {
+                run(dir, ["ls-files", "--", name]).is_ok_and(|out| !out.trim().is_empty())
+            }
+        })
+        .collect()
+}
+
+/// Commit the combo files of `dir` with `message` if they changed. The
+/// first commit adds a `.gitignore` for the files that stay out of git.
+fn commit(dir: &Path, message: &str) -> Result<(), String> {
+    let gitignore = dir.join(".gitignore");
+    if !gitignore.exists() {
+        std::fs::write(&gitignore, GITIGNORE)
+            .map_err(|e| format!("Cannot write .gitignore: {e}"))?;
+    }
+    let files = combo_files(dir);
+    if files.is_empty() {
+        return Ok(());
+    }
+    run(
+        dir,
+        ["add", "-A", "--"].into_iter().chain(files.iter().copied()),
+    )?;
+    let unchanged = git(
+        dir,
+        ["diff", "--cached", "--quiet", "--"]
+            .into_iter()
+            .chain(files.iter().copied()),
+    )
+    .is_ok_and(|o| o.status.success());
+    if unchanged {
+        return Ok(());
+    }
+    run(
+        dir,
+        ["commit", "-q", "-m", message, "--"]
+            .into_iter()
+            .chain(files.iter().copied()),
+    )?;
+    Ok(())
+}
+
+/// Directory of the global library.
+fn library_dir(location: &Location) -> PathBuf {
+    let file = store_paths(location).file;
+    file.parent()
+        .expect("Combo file has no parent directory")
+        .to_path_buf()
+}
+
+/// Commit a change described by `message` to the global library, if its
+/// directory is in a git repository. Project libraries are committed along
+/// with their project, by hand.
+pub fn commit_change(location: &Location, message: &str) {
+    if !matches!(location, Location::Global(_)) {
+        return;
+    }
+    let dir = library_dir(location);
+    if !in_repository(&dir) {
+        return;
+    }
+    if let Err(e) = commit(&dir, message) {
+        eprintln!("Cannot commit the change to git: {e}");
+    }
+}
+
+/// Commit any pending changes to the global library, rebase them onto its
+/// git remote and push the result. When a combo was changed differently on
+/// both sides, `choose` is asked which version to keep, given mine, theirs
+/// (None if deleted) and the name mine would be kept under next to theirs.
+/// If it returns None, the sync is cancelled and the rebase aborted.
+pub fn sync(
+    location: &Location,
+    mut choose: impl FnMut(Option<&Combo>, Option<&Combo>, &str) -> Option<Merge>,
+) -> Result<(), StoreError> {
+    let _lock = lock_store(location);
+    let dir = library_dir(location);
+    if !in_repository(&dir) {
+        return Err(StoreError::Sync(format!(
+            "{} is not in a git repository",
+            dir.display()
+        )));
+    }
+    commit(&dir, "sync combos").map_err(StoreError::Sync)?;
+    let prefix = run(&dir, ["rev-parse", "--show-prefix"]).map_err(StoreError::Sync)?;
+    let prefix = prefix.trim();
+
+    let mut guard = RebaseGuard {
+        dir: &dir,
+        armed: true,
+    };
+    let mut outcome = run(&dir, ["pull", "--rebase", "--autostash"]);
+    while let Err(e) = outcome {
+        if !rebasing(&dir) {
+            return Err(StoreError::Sync(e));
+        }
+        let conflicts =
+            run(&dir, ["diff", "--name-only", "--diff-filter=U"]).map_err(StoreError::Sync)?;
+        let conflicts: Vec<&str> = conflicts.lines().collect();
+        if conflicts.is_empty() {
+            // Keeping their version of every combo left the commit empty.
+            outcome = run(&dir, ["-c", "core.editor=true", "rebase", "--skip"]);
+            continue;
+        }
+        for path in conflicts {
+            let kind = path.strip_prefix(prefix).and_then(|name| match name {
+                "combos.yaml" => Some((name, Kind::File)),
+                "combos.db" => Some((name, Kind::Database)),
+                _ if name.starts_with("combos/") && name.ends_with(".yaml") => {
+                    Some((name, Kind::Combo))
+                }
+                _ => None,
+            });
+            let resolved = match kind {
+                Some((name, kind)) => resolve(&dir, name, kind, &mut choose),
+                None => Err(StoreError::Sync(format!(
+                    "{path} conflicts and is not a combo file; sync it with git by hand"
+                ))),
+            };
+            resolved?;
+        }
+        outcome = run(&dir, ["-c", "core.editor=true", "rebase", "--continue"]);
+    }
+    guard.armed = false;
+    run(&dir, ["push"]).map_err(StoreError::Sync)?;
+    Ok(())
+}
+
+/// Aborts a rebase left stopped partway in `dir` when dropped, unless
+/// disarmed, so that neither an error nor a panic while resolving conflicts
+/// leaves the repository mid-rebase.
+struct RebaseGuard<'a> {
+    dir: &'a Path,
+    armed: bool,
+}
+
+impl Drop for RebaseGuard<'_> {
+    fn drop(&mut self) {
+        if self.armed && rebasing(self.dir) {
+            let _ = run(self.dir, ["rebase", "--abort"]);
+        }
+    }
+}
+
+/// Whether a rebase stopped partway in the repository of `dir`.
+fn rebasing(dir: &Path) -> bool {
+    ["rebase-merge", "rebase-apply"].iter().any(|name| {
+        run(dir, ["rev-parse", "--git-path", name]).is_ok_and(|path| dir.join(path.trim()).exists())
+    })
+}
+
+/// Merge the conflicting versions of the combo file `name` in `dir` combo by
+/// combo, write the result and mark it resolved.
+fn resolve(
+    dir: &Path,
+    name: &str,
+    kind: Kind,
+    choose: &mut impl FnMut(Option<&Combo>, Option<&Combo>, &str) -> Option<Merge>,
+) -> Result<(), StoreError> {
+    // While rebasing, "ours" (stage 2) is the remote and "theirs" (stage 3)
+    // the local commit being replayed.
+    let base = read_stage(dir, name, 1, kind)?;
+    let theirs = read_stage(dir, name, 2, kind)?;
+    let mine = read_stage(dir, name, 3, kind)?;
+    let combos =
+        |doc: &Option<ComboFile>| doc.as_ref().map(|d| d.combos.clone()).unwrap_or_default();
+    let taken = |candidate: &str| match kind {
+        Kind::Combo => dir
+            .join("combos")
+            .join(format!("{}.yaml", encode_file_name(candidate)))
+            .exists(),
+        Kind::File | Kind::Database => false,
+    };
+    let merged = merge_combos(
+        &combos(&base),
+        &combos(&mine),
+        &combos(&theirs),
+        taken,
+        choose,
+    )
+    .ok_or_else(|| StoreError::Sync("cancelled".to_string()))?;
+    let path = dir.join(name);
+    let mut written = vec![name.to_string()];
+    match kind {
+        Kind::File | Kind::Database => {
+            let mut doc = mine.or(theirs).unwrap_or(ComboFile {
+                version: CURRENT_VERSION,
+                ..Default::default()
+            });
+            doc.combos = merged;
+            match kind {
+                Kind::File => FileStorage { path, state: None }.save(&doc)?,
+                _ => SqliteStorage { path }.save(&doc)?,
+            }
+        }
+        Kind::Combo => {
+            if path.exists() {
+                std::fs::remove_file(&path).map_err(|e| write_error(&path, e))?;
+            }
+            let paths = combo_file_paths(&dir.join("combos"), &merged)?;
+            for (combo, path) in merged.iter().zip(paths) {
+                let text =
+                    serde_yaml::to_string(&definition(combo)).expect("Failed to serialize combo");
+                write_atomic(&path, text.as_bytes()).map_err(|e| write_error(&path, e))?;
+                let file = path
+                    .strip_prefix(dir)
+                    .expect("combo file outside its directory");
+                written.push(file.to_string_lossy().into_owned());
+            }
+        }
+    }
+    run(
+        dir,
+        ["add", "-A", "--"]
+            .into_iter()
+            .chain(written.iter().map(String::as_str)),
+    )
+    .map_err(StoreError::Sync)?;
+    Ok(())
+}
+
+/// The combos in stage `stage` of the conflicting file `name`, or None if
+/// that side deleted it.
+fn read_stage(
+    dir: &Path,
+    name: &str,
+    stage: u8,
+    kind: Kind,
+) -> Result<Option<ComboFile>, StoreError> {
+    let Ok(output) = git(dir, ["show".to_string(), format!(":{stage}:./{name}")]) else {
+        return Ok(None);
+    };
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let mut tmp = NamedTempFile::new().map_err(|e| write_error(&std::env::temp_dir(), e))?;
+    let path = tmp.path().to_path_buf();
+    tmp.write_all(&output.stdout)
+        .map_err(|e| write_error(&path, e))?;
+    let doc = match kind {
+        Kind::File => read_document(&path)?,
+        Kind::Database => SqliteStorage { path }.load()?,
+        Kind::Combo => {
+            let combo = serde_yaml::from_slice(&output.stdout)
+                .map_err(|e| StoreError::Sync(format!("cannot read {name} in the merge: {e}")))?;
+            ComboFile {
+                version: CURRENT_VERSION,
+                combos: vec![combo],
+                ..Default::default()
+            }
+        }
+    };
+    Ok(Some(doc))
+}
+
+/// Three-way merge of two changed versions of `base`, combo by combo. Combos
+/// changed on one side only take that side; `choose` picks between those
+/// changed on both. Returns None if `choose` cancels.
+fn merge_combos(
+    base: &[Combo],
+    mine: &[Combo],
+    theirs: &[Combo],
+    taken: impl Fn(&str) -> bool,
+    choose: &mut impl FnMut(Option<&Combo>, Option<&Combo>, &str) -> Option<Merge>,
+) -> Option<Vec<Combo>> {
+    let find = |combos: &[Combo], name: &str| combos.iter().find(|c| c.name == name).cloned();
+    let same = |a: &Option<Combo>, b: &Option<Combo>| match (a, b) {
+        (Some(a), Some(b)) => definition(a) == definition(b),
+        (None, None) => true,
+        _ => false,
+    };
+    let mut names: Vec<&str> = theirs.iter().map(|c| c.name.as_str()).collect();
+    for combo in mine {
+        if !names.contains(&combo.name.as_str()) {
+            names.push(&combo.name);
+        }
+    }
+    let mut merged = Vec::new();
+    for name in names {
+        let (b, m, t) = (find(base, name), find(mine, name), find(theirs, name));
+        // Mine comes first when they agree, for its usage data.
+        if same(&m, &t) || same(&b, &t) {
+            merged.extend(m);
+        } else if same(&b, &m) {
+            merged.extend(t);
+        } else {
+            let is_free = |candidate: &str| {
+                ![mine, theirs]
+                    .iter()
+                    .any(|side| side.iter().any(|c| c.name == candidate))
+                    && !taken(candidate)
+            };
+            let renamed = (0..)
+                .map(|i| format!("{name}_{i}"))
+                .find(|candidate| is_free(candidate))
+                .expect("Ran out of combo names");
+            match choose(m.as_ref(), t.as_ref(), &renamed)? {
+                Merge::Mine => merged.extend(m),
+                Merge::Theirs => merged.extend(t),
+                Merge::Both => {
+                    merged.extend(t);
+                    merged.extend(m.map(|m| Combo { name: renamed, ..m }));
+                }
+            }
+        }
+    }
+    Some(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn combo(name: &str, command: &str) -> Combo {
+        Combo {
+            name: name.to_string(),
+            commands: vec![command.to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn commands(combos: &[Combo]) -> Vec<(&str, &str)> {
+        combos
+            .iter()
+            .map(|c| (c.name.as_str(), c.commands[0].as_str()))
+            .collect()
+    }
+
+    /// Merge, failing the test if asked to choose.
+    /// Two clones of a bare remote, each with its library in `lib/` and
+    /// both at a first commit holding x and y.
+    fn clones(root: &Path) -> (PathBuf, PathBuf) {
+        let remote = root.join("remote.git");
+        run(root, ["init", "-q", "--bare", remote.to_str().unwrap()]).unwrap();
+        let clone = |name: &str| {
+            let dir = root.join(name);
+            std::fs::create_dir_all(dir.join("lib")).unwrap();
+            run(&dir, ["init", "-q"]).unwrap();
+            run(&dir, ["config", "user.name", "test"]).unwrap();
+            run(&dir, ["config", "user.email", "test@example.com"]).unwrap();
+            run(&dir, ["remote", "add", "origin", remote.to_str().unwrap()]).unwrap();
+            dir.join("lib")
+        };
+        let a = clone("a");
+        save(&a, &[combo("x", "ls"), combo("y", "pwd")]);
+        commit(&a, "first").unwrap();
+        run(&a, ["push", "-q", "-u", "origin", "HEAD"]).unwrap();
+        let b = clone("b");
+        let branch = run(&a, ["branch", "--show-current"]).unwrap();
+        let branch = branch.trim();
+        run(&b, ["fetch", "-q", "origin"]).unwrap();
+        run(
+            &b,
+            [
+                "checkout",
+                "-q",
+                "-B",
+                branch,
+                "--track",
+                &format!("origin/{branch}"),
+            ],
+        )
+        .unwrap();
+        (a, b)
+    }
+
+    fn save(lib: &Path, combos: &[Combo]) {
+        let storage = FileStorage {
+            path: lib.join("combos.yaml"),
+            state: None,
+        };
+        let doc = ComboFile {
+            version: CURRENT_VERSION,
+            combos: combos.to_vec(),
+            ..Default::default()
+        };
+        storage.save(&doc).unwrap();
+    }
+
+    fn load(lib: &Path) -> Vec<Combo> {
+        let storage = FileStorage {
+            path: lib.join("combos.yaml"),
+            state: None,
+        };
+        storage.load().unwrap().combos
+    }
+
+    fn sync_lib(
+        lib: &Path,
+        choose: impl FnMut(Option<&Combo>, Option<&Combo>, &str) -> Option<Merge>,
+    ) -> Result<(), StoreError> {
+        sync(
+            &Location::Global(Some(lib.to_string_lossy().into_owned())),
+            choose,
+        )
+    }
+
+    fn unasked(_: Option<&Combo>, _: Option<&Combo>, name: &str) -> Option<Merge> {
+        panic!("asked to choose for {name}")
+    }
+
+    fn merge_unasked(base: &[Combo], mine: &[Combo], theirs: &[Combo]) -> Vec<Combo> {
+        merge_combos(base, mine, theirs, |_| false, &mut |_, _, name| {
+            panic!("asked to choose for {name}")
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn takes_the_side_that_changed() {
+        let base = [combo("a", "ls"), combo("b", "pwd")];
+        let mine = [combo("a", "ls -l"), combo("b", "pwd")];
+        let theirs = [combo("a", "ls"), combo("b", "pwd -P"), combo("c", "date")];
+        let merged = merge_unasked(&base, &mine, &theirs);
+        assert_eq!(
+            commands(&merged),
+            [("a", "ls -l"), ("b", "pwd -P"), ("c", "date")]
+        );
+    }
+
+    #[test]
+    fn usage_changes_do_not_conflict() {
+        let base = [combo("a", "ls")];
+        let mine = [Combo {
+            use_count: 3,
+            last_used: 20,
+            ..combo("a", "ls")
+        }];
+        let theirs = [Combo {
+            use_count: 1,
+            last_used: 10,
+            ..combo("a", "ls")
+        }];
+        let merged = merge_unasked(&base, &mine, &theirs);
+        assert_eq!(merged[0].use_count, 3);
+
+        let merged = merge_unasked(&base, &base, &theirs);
+        assert_eq!(merged[0].use_count, 0);
+    }
+
+    #[test]
+    fn asks_when_both_sides_changed() {
+        let base = [combo("a", "ls")];
+        let mine = [combo("a", "ls -l")];
+        let theirs = [combo("a", "ls -a"), combo("a_0", "true")];
+        let merge = |choice: fn() -> Merge| {
+            let mut asked = None;
+            let merged = merge_combos(&base, &mine, &theirs, |n| n == "a_1", &mut |m, t, name| {
+                asked = Some((m.cloned(), t.cloned(), name.to_string()));
+                Some(choice())
+            })
+            .unwrap();
+            let (m, t, renamed) = asked.expect("not asked to choose");
+            assert_eq!(m.unwrap().commands, ["ls -l"]);
+            assert_eq!(t.unwrap().commands, ["ls -a"]);
+            // a_0 is in use on the remote and a_1 is taken here.
+            assert_eq!(renamed, "a_2");
+            merged
+        };
+        assert_eq!(
+            commands(&merge(|| Merge::Mine)),
+            [("a", "ls -l"), ("a_0", "true")]
+        );
+        assert_eq!(
+            commands(&merge(|| Merge::Theirs)),
+            [("a", "ls -a"), ("a_0", "true")]
+        );
+        assert_eq!(
+            commands(&merge(|| Merge::Both)),
+            [("a", "ls -a"), ("a_2", "ls -l"), ("a_0", "true")]
+        );
+    }
+
+    #[test]
+    fn asks_when_one_side_deleted_what_the_other_changed() {
+        let base = [combo("a", "ls")];
+        let changed = [combo("a", "ls -l")];
+        let mut asked = Vec::new();
+        let mut choose = |m: Option<&Combo>, t: Option<&Combo>, _: &str| {
+            asked.push((m.is_some(), t.is_some()));
+            Some(Merge::Mine)
+        };
+        let merged = merge_combos(&base, &[], &changed, |_| false, &mut choose).unwrap();
+        assert!(merged.is_empty());
+        let merged = merge_combos(&base, &changed, &[], |_| false, &mut choose).unwrap();
+        assert_eq!(commands(&merged), [("a", "ls -l")]);
+        assert_eq!(asked, [(false, true), (true, false)]);
+    }
+
+    #[test]
+    fn cancelling_stops_the_merge() {
+        let base = [combo("a", "ls")];
+        let merged = merge_combos(
+            &base,
+            &[combo("a", "ls -l")],
+            &[combo("a", "ls -a")],
+            |_| false,
+            &mut |_, _, _| None,
+        );
+        assert!(merged.is_none());
+    }
+
+    #[test]
+    fn sync_merges_a_combo_changed_on_both_sides() {
+        let root = tempfile::tempdir().unwrap();
+        let (a, b) = clones(root.path());
+        save(&a, &[combo("x", "ls -l"), combo("y", "pwd")]);
+        sync_lib(&a, unasked).unwrap();
+
+        save(&b, &[combo("x", "ls -a"), combo("y", "pwd -P")]);
+        let mut asked = None;
+        sync_lib(&b, |m, t, name| {
+            asked = Some((m.cloned(), t.cloned(), name.to_string()));
+            Some(Merge::Both)
+        })
+        .unwrap();
+        // Mine is the local commit being replayed, theirs the remote.
+        let (m, t, renamed) = asked.expect("not asked to choose");
+        assert_eq!(m.unwrap().commands, ["ls -a"]);
+        assert_eq!(t.unwrap().commands, ["ls -l"]);
+        assert_eq!(renamed, "x_0");
+        let merged = [("x", "ls -l"), ("x_0", "ls -a"), ("y", "pwd -P")];
+        assert_eq!(commands(&load(&b)), merged);
+        assert!(!rebasing(&b));
+
+        sync_lib(&a, unasked).unwrap();
+        assert_eq!(commands(&load(&a)), merged);
+    }
+
+    #[test]
+    fn sync_skips_a_commit_left_empty_by_keeping_theirs() {
+        let root = tempfile::tempdir().unwrap();
+        let (a, b) = clones(root.path());
+        save(&a, &[combo("x", "ls -l"), combo("y", "pwd")]);
+        sync_lib(&a, unasked).unwrap();
+
+        // The apply backend stops at a commit that resolving emptied, as
+        // older versions of git do, instead of dropping it.
+        run(&b, ["config", "rebase.backend", "apply"]).unwrap();
+        save(&b, &[combo("x", "ls -a"), combo("y", "pwd")]);
+        sync_lib(&b, |_, _, _| Some(Merge::Theirs)).unwrap();
+        assert_eq!(commands(&load(&b)), [("x", "ls -l"), ("y", "pwd")]);
+        assert!(!rebasing(&b));
+        assert_eq!(
+            run(&b, ["rev-parse", "HEAD"]).unwrap(),
+            run(&b, ["rev-parse", "@{u}"]).unwrap()
+        );
+    }
+
+    #[test]
+    fn a_failed_sync_aborts_the_rebase() {
+        let root = tempfile::tempdir().unwrap();
+        let (a, b) = clones(root.path());
+        save(&a, &[combo("x", "ls -l"), combo("y", "pwd")]);
+        sync_lib(&a, unasked).unwrap();
+        save(&b, &[combo("x", "ls -a"), combo("y", "pwd")]);
+
+        assert!(sync_lib(&b, |_, _, _| None).is_err());
+        assert!(!rebasing(&b));
+        assert_eq!(commands(&load(&b)), [("x", "ls -a"), ("y", "pwd")]);
+
+        let panicked = std::panic::catch_unwind(|| sync_lib(&b, |_, _, _| panic!("interrupted")));
+        assert!(panicked.is_err());
+        assert!(!rebasing(&b));
+        assert_eq!(commands(&load(&b)), [("x", "ls -a"), ("y", "pwd")]);
+    }
+}
@@ -0,0 +1,1010 @@
+mod git;
+mod sqlite;
+mod yaml;
+
+use crate::types::{Combo, Revision};
+use std::fmt;
+use std::fs::{self, create_dir_all, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::{Local, Utc};
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use tempfile::NamedTempFile;
+
+use git::commit_change;
+use sqlite::SqliteStorage;
+use yaml::{DirectoryStorage, FileStorage};
+
+pub use git::sync;
+
+/// Number of backups of the combo file kept in the `backups` directory.
+const MAX_BACKUPS: usize = 20;
+
+/// Layout version of the combo file written by this comboman.
+/// 1: a bare list of combos.
+/// 2: a document with `version` and `combos` fields.
+const CURRENT_VERSION: u64 = 2;
+
+/// The combo file. Fields this version does not know about, at the top level
+/// or in a combo, are read into `extra` and written back unchanged.
+#[derive(Default, Serialize, Deserialize)]
+struct ComboFile {
+    version: u64,
+    combos: Vec<Combo>,
+    #[serde(flatten)]
+    extra: Mapping,
+}
+
+/// Combo fields that change whenever a combo is used. In the directory
+/// layout they are kept out of the combo files, in `state.yaml`.
+const VOLATILE_FIELDS: &[&str] = &["last_used", "use_count", "total_runtime"];
+
+/// Written to a combo directory converted to the directory layout or first
+/// committed to git, so that only the combos themselves are committed.
+const GITIGNORE: &str = "state.yaml\nbackups/\ncombos.lock\ntrash.yaml\nundo.yaml\n";
+
+/// How the combos in a combo directory are stored.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// All combos in one `combos.yaml`.
+    File,
+    /// One YAML file per combo in a `combos` directory.
+    Directory,
+    /// An SQLite database, `combos.db`.
+    Sqlite,
+}
+
+/// Where the combos of a library are kept. Callers hold the library's lock.
+trait Storage {
+    /// Read all combos.
+    fn load(&self) -> Result<ComboFile, StoreError>;
+
+    /// Replace the stored combos with those in `doc`.
+    fn save(&self, doc: &ComboFile) -> Result<(), StoreError>;
+
+    /// Delete the stored combos, once they have been converted to another
+    /// layout.
+    fn remove(&self) -> Result<(), StoreError>;
+
+    /// The combo called `name`, if any.
+    fn find(&self, name: &str) -> Result<Option<Combo>, StoreError> {
+        Ok(self.load()?.combos.into_iter().find(|c| c.name == name))
+    }
+
+    /// The combos tagged `tag`, in order.
+    fn tagged(&self, tag: &str) -> Result<Vec<Combo>, StoreError> {
+        let mut combos = self.load()?.combos;
+        combos.retain(|c| c.tags.iter().any(|t| t == tag));
+        Ok(combos)
+    }
+
+    /// Append `combo` unless its name is taken. Returns whether it was added.
+    fn add(&self, combo: &Combo) -> Result<bool, StoreError> {
+        let mut doc = self.load()?;
+        if doc.combos.iter().any(|c| c.name == combo.name) {
+            return Ok(false);
+        }
+        doc.combos.push(combo.clone());
+        self.save(&doc)?;
+        Ok(true)
+    }
+
+    /// Record that the combo `name` was just run and took `runtime`.
+    /// Returns whether there is such a combo.
+    fn record_use(&self, name: &str, runtime: Duration) -> Result<bool, StoreError> {
+        let mut doc = self.load()?;
+        let Some(c) = doc.combos.iter_mut().find(|c| c.name == name) else {
+            return Ok(false);
+        };
+        c.last_used = Utc::now().timestamp();
+        c.use_count += 1;
+        c.total_runtime += runtime.as_secs_f64();
+        self.save(&doc)?;
+        Ok(true)
+    }
+}
+
+/// A combo removed by `comboman delete`, kept until the trash is purged.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TrashedCombo {
+    pub deleted_at: i64,
+    #[serde(flatten)]
+    pub combo: Combo,
+}
+
+/// What the last change to a library did, so that it can be undone.
+#[derive(Serialize, Deserialize)]
+struct UndoRecord {
+    description: String,
+    at: i64,
+    /// The combos the change removed or modified, as they were before.
+    before: Vec<Combo>,
+    /// Names of the combos the change added or modified.
+    added: Vec<String>,
+}
+
+/// Why the combo store could not be read.
+#[derive(Debug)]
+pub enum StoreError {
+    Io { path: PathBuf, source: io::Error },
+    /// The file is not a valid list of combos; nothing is written over it.
+    Parse {
+        path: PathBuf,
+        line: Option<usize>,
+        column: Option<usize>,
+        message: String,
+    },
+    /// `comboman restore` was given a backup that does not exist.
+    NoBackup(String),
+    Database { path: PathBuf, source: rusqlite::Error },
+    /// Writing or removing a file failed, e.g. because the disk is full.
+    Write { path: PathBuf, source: io::Error },
+    /// `comboman sync` failed; the message is git's, or says why.
+    Sync(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Io { path, source } => write!(f, "Cannot read {}: {source}", path.display()),
+            StoreError::Parse { path, line: Some(line), column: Some(column), message } => {
+                write!(f, "{}:{line}:{column}: {message}", path.display())
+            }
+            StoreError::Parse { path, message, .. } => write!(f, "{}: {message}", path.display()),
+            StoreError::NoBackup(name) => write!(f, "No backup named '{name}'"),
+            StoreError::Database { path, source } => write!(f, "{}: {source}", path.display()),
+            StoreError::Write { path, source } => {
+                write!(f, "Cannot write {}: {source}", path.display())
+            }
+            StoreError::Sync(message) => write!(f, "Cannot sync combos: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl StoreError {
+    /// The file the error is about, if any.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            StoreError::Io { path, .. }
+            | StoreError::Parse { path, .. }
+            | StoreError::Database { path, .. }
+            | StoreError::Write { path, .. } => Some(path),
+            StoreError::NoBackup(_) | StoreError::Sync(_) => None,
+        }
+    }
+}
+
+/// A combo library.
+#[derive(Clone, Debug)]
+pub enum Location {
+    /// The user's library, in the config directory or `--combo-directory`.
+    Global(Option<String>),
+    /// A project's library: a `.comboman` directory, laid out like the
+    /// global one, or a single `.comboman.yaml` combo file.
+    Project(PathBuf),
+}
+
+/// The files that make up a combo library.
+struct StorePaths {
+    /// Combo file of the file layout.
+    file: PathBuf,
+    /// Combos directory of the directory layout. A library kept in a single
+    /// `.comboman.yaml` has none.
+    combos_dir: Option<PathBuf>,
+    /// Database of the SQLite layout, which a `.comboman.yaml` library
+    /// doesn't have either.
+    database: Option<PathBuf>,
+    /// Usage data of the combos, in the directory layout and for projects.
+    state: PathBuf,
+    /// Whether the library belongs to a project, whose files are shared and
+    /// so never hold usage data.
+    shared: bool,
+    lock: PathBuf,
+    backups: PathBuf,
+    /// Combos removed by `comboman delete`.
+    trash: PathBuf,
+    /// How to undo the last change.
+    undo: PathBuf,
+}
+
+/// Returns the paths of the library at `location`, ensuring its directory
+/// exists.
+fn store_paths(location: &Location) -> StorePaths {
+    let dir = match location {
+        Location::Global(Some(dir)) => PathBuf::from(dir),
+        Location::Global(None) => {
+            let config_dir = dirs::config_dir().expect("Cannot find config directory");
+            config_dir.join("comboman")
+        }
+        Location::Project(path) => {
+            // Keep comboman's own files and the user's usage data out of the
+            // project.
+            let data_dir = dirs::data_dir().expect("Cannot find data directory");
+            let dir = data_dir
+                .join("comboman")
+                .join("projects")
+                .join(encode_file_name(&path.to_string_lossy()));
+            create_dir_all(&dir).expect("Cannot create comboman directory");
+            let library = path.is_dir().then_some(path);
+            return StorePaths {
+                file: library.map_or_else(|| path.clone(), |l| l.join("combos.yaml")),
+                combos_dir: library.map(|l| l.join("combos")),
+                database: library.map(|l| l.join("combos.db")),
+                state: dir.join("state.yaml"),
+                shared: true,
+                lock: dir.join("combos.lock"),
+                backups: dir.join("backups"),
+                trash: dir.join("trash.yaml"),
+                undo: dir.join("undo.yaml"),
+            };
+        }
+    };
+    create_dir_all(&dir).expect("Cannot create comboman directory");
+    StorePaths {
+        file: dir.join("combos.yaml"),
+        combos_dir: Some(dir.join("combos")),
+        database: Some(dir.join("combos.db")),
+        state: dir.join("state.yaml"),
+        shared: false,
+        lock: dir.join("combos.lock"),
+        backups: dir.join("backups"),
+        trash: dir.join("trash.yaml"),
+        undo: dir.join("undo.yaml"),
+    }
+}
+
+/// The nearest project library in `start` or one of its parents: a
+/// `.comboman` directory or a `.comboman.yaml` file.
+pub fn find_project_library(start: &Path) -> Option<PathBuf> {
+    start.ancestors().find_map(|dir| {
+        [".comboman", ".comboman.yaml"]
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.exists())
+    })
+}
+
+/// The library `combo` was loaded from.
+pub fn combo_location(combo: &Combo, global: &Location) -> Location {
+    match &combo.origin {
+        Some(path) => Location::Project(path.clone()),
+        None => global.clone(),
+    }
+}
+
+/// Load the combos of the `project` library, if any, marked with their
+/// origin, followed by those of the global library. A project combo hides a
+/// global one with the same name.
+pub fn load_all_combos(global: &Location, project: Option<&Path>) -> Result<Vec<Combo>, StoreError> {
+    let mut combos = Vec::new();
+    if let Some(project) = project {
+        combos = load_combos(&Location::Project(project.to_path_buf()))?;
+        for combo in &mut combos {
+            combo.origin = Some(project.to_path_buf());
+        }
+    }
+    for combo in load_combos(global)? {
+        if !combos.iter().any(|c| c.name == combo.name) {
+            combos.push(combo);
+        }
+    }
+    Ok(combos)
+}
+
+/// The combo called `name` in the `project` library, if any, or else in the
+/// global library, looked up without loading the others.
+pub fn find_combo(
+    global: &Location,
+    project: Option<&Path>,
+    name: &str,
+) -> Result<Option<Combo>, StoreError> {
+    if let Some(project) = project {
+        if let Some(mut combo) = storage(&Location::Project(project.to_path_buf())).find(name)? {
+            combo.origin = Some(project.to_path_buf());
+            return Ok(Some(combo));
+        }
+    }
+    storage(global).find(name)
+}
+
+/// The combos tagged `tag`, as `load_all_combos` would list them.
+pub fn tagged_combos(
+    global: &Location,
+    project: Option<&Path>,
+    tag: &str,
+) -> Result<Vec<Combo>, StoreError> {
+    let project =
+        project.map(|p| (storage(&Location::Project(p.to_path_buf())), p.to_path_buf()));
+    let mut combos = Vec::new();
+    if let Some((storage, path)) = &project {
+        combos = storage.tagged(tag)?;
+        for combo in &mut combos {
+            combo.origin = Some(path.clone());
+        }
+    }
+    for combo in storage(global).tagged(tag)? {
+        // A project combo hides a global one even when it isn't tagged.
+        let hidden = match &project {
+            Some((storage, _)) => storage.find(&combo.name)?.is_some(),
+            None => false,
+        };
+        if !hidden {
+            combos.push(combo);
+        }
+    }
+    Ok(combos)
+}
+
+/// Name of the project a project library belongs to, for display: the
+/// directory holding it.
+pub fn project_name(library: &Path) -> String {
+    let project = library.parent().and_then(Path::file_name).unwrap_or(library.as_os_str());
+    project.to_string_lossy().into_owned()
+}
+
+pub fn load_combos(location: &Location) -> Result<Vec<Combo>, StoreError> {
+    storage(location).load().map(|doc| doc.combos)
+}
+
+/// The SQLite layout is used when the combo directory has a `combos.db`,
+/// the directory layout when it has a `combos` subdirectory, and the file
+/// layout otherwise.
+fn layout(location: &Location) -> Layout {
+    let paths = store_paths(location);
+    if paths.database.is_some_and(|db| db.exists()) {
+        Layout::Sqlite
+    } else if paths.combos_dir.is_some_and(|dir| dir.is_dir()) {
+        Layout::Directory
+    } else {
+        Layout::File
+    }
+}
+
+/// The storage of the library at `location`, in the layout it uses.
+fn storage(location: &Location) -> Box<dyn Storage> {
+    storage_in(location, layout(location))
+}
+
+fn storage_in(location: &Location, layout: Layout) -> Box<dyn Storage> {
+    let paths = store_paths(location);
+    let only_file = "A .comboman.yaml library can only be stored in that file";
+    match layout {
+        Layout::File => Box::new(FileStorage {
+            path: paths.file,
+            state: paths.shared.then_some(paths.state),
+        }),
+        Layout::Directory => Box::new(DirectoryStorage {
+            dir: paths.combos_dir.expect(only_file),
+            state: paths.state,
+        }),
+        Layout::Sqlite => Box::new(SqliteStorage { path: paths.database.expect(only_file) }),
+    }
+}
+
+/// Serialize `combos` as a bundle for `comboman import`: a combo file
+/// without the usage data of whoever exported them.
+pub fn bundle_text(combos: &[Combo]) -> String {
+    let combos = combos
+        .iter()
+        .map(|c| Combo {
+            last_used: 0,
+            use_count: 0,
+            total_runtime: 0.0,
+            ..c.clone()
+        })
+        .collect();
+    let doc = ComboFile { version: CURRENT_VERSION, combos, ..Default::default() };
+    serde_yaml::to_string(&doc).expect("Failed to serialize combos")
+}
+
+/// Read the combos in a combo file, backup or bundle of any layout version.
+pub fn read_combo_file(path: &Path) -> Result<Vec<Combo>, StoreError> {
+    read_document(path).map(|doc| doc.combos)
+}
+
+/// Read a combo file, migrating older layouts to the current one. Files from
+/// a newer comboman keep their version, so saving them doesn't claim they
+/// follow an older layout.
+fn read_document(path: &Path) -> Result<ComboFile, StoreError> {
+    let text = fs::read_to_string(path)
+        .map_err(|source| StoreError::Io { path: path.to_path_buf(), source })?;
+    let parse_error = |e: serde_yaml::Error| parse_error(path, e);
+    // Parse generically first to find the layout; deserializing the text
+    // again afterwards keeps line numbers in type errors.
+    let value: Value = serde_yaml::from_str(&text).map_err(parse_error)?;
+    let version = match &value {
+        Value::Null => return Ok(ComboFile { version: CURRENT_VERSION, ..Default::default() }),
+        Value::Sequence(_) => 1,
+        Value::Mapping(m) => m.get("version").and_then(Value::as_u64).unwrap_or(0),
+        _ => 0,
+    };
+    match version {
+        1 => {
+            let combos = serde_yaml::from_str(&text).map_err(parse_error)?;
+            Ok(ComboFile { version: CURRENT_VERSION, combos, ..Default::default() })
+        }
+        2.. => serde_yaml::from_str(&text).map_err(parse_error),
+        _ => Err(StoreError::Parse {
+            path: path.to_path_buf(),
+            line: None,
+            column: None,
+            message: "expected a list of combos or a document with a version".to_string(),
+        }),
+    }
+}
+
+/// Turn a combo name into a file name that is valid everywhere and maps back
+/// to a single name: bytes other than ASCII letters, digits, `_` and `-`
+/// are written as `%XX`, as is a leading `.`.
+fn encode_file_name(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+    for (i, b) in name.bytes().enumerate() {
+        if b.is_ascii_alphanumeric() || b == b'_' || b == b'-' || (b == b'.' && i > 0) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    encoded
+}
+
+/// The paths in `dir` of the files holding `combos` in the directory layout.
+/// Names differing only in case, such as `Build` and `build`, would share a
+/// file on case-insensitive file systems, so they are refused.
+fn combo_file_paths(dir: &Path, combos: &[Combo]) -> Result<Vec<PathBuf>, StoreError> {
+    let mut paths: Vec<PathBuf> = Vec::with_capacity(combos.len());
+    for combo in combos {
+        let file = format!("{}.yaml", encode_file_name(&combo.name));
+        let shared =
+            |path: &PathBuf| path.file_name().is_some_and(|f| f.eq_ignore_ascii_case(&file));
+        if let Some(other) = paths.iter().position(shared) {
+            let message = format!(
+                "combos '{}' and '{}' differ only in case; rename one of them",
+                combos[other].name, combo.name
+            );
+            let source = io::Error::new(io::ErrorKind::AlreadyExists, message);
+            return Err(write_error(&dir.join(file), source));
+        }
+        paths.push(dir.join(file));
+    }
+    Ok(paths)
+}
+
+/// Move the combos in the combo directory to another layout. The old layout
+/// is removed once the new one is written.
+pub fn convert_layout(location: &Location, to: Layout) -> Result<(), StoreError> {
+    let _lock = lock_store(location);
+    let from = layout(location);
+    let doc = storage_in(location, from).load()?;
+    backup_combos(location)?;
+    storage_in(location, to).save(&doc)?;
+    if to == Layout::Directory {
+        let gitignore = store_paths(location).file.with_file_name(".gitignore");
+        if !gitignore.exists() {
+            fs::write(&gitignore, GITIGNORE).map_err(|e| write_error(&gitignore, e))?;
+        }
+    }
+    if from != to {
+        storage_in(location, from).remove()?;
+    }
+    let name = match to {
+        Layout::File => "file",
+        Layout::Directory => "directory",
+        Layout::Sqlite => "sqlite",
+    };
+    commit_change(location, &format!("convert to the {name} layout"));
+    Ok(())
+}
+
+fn parse_error(path: &Path, e: serde_yaml::Error) -> StoreError {
+    let location = e.location();
+    let mut message = e.to_string();
+    if let Some(l) = &location {
+        // The location is reported separately.
+        let suffix = format!(" at line {} column {}", l.line(), l.column());
+        if message.ends_with(&suffix) {
+            message.truncate(message.len() - suffix.len());
+        }
+    }
+    StoreError::Parse {
+        path: path.to_path_buf(),
+        line: location.as_ref().map(|l| l.line()),
+        column: location.as_ref().map(|l| l.column()),
+        message,
+    }
+}
+
+
+/// Copy the current combo file, if any, into the backup directory and prune
+/// all but the newest `MAX_BACKUPS` copies. In the other layouts the combos
+/// are backed up as a single combo file, if they can be read.
+fn backup_combos(location: &Location) -> Result<(), StoreError> {
+    let dir = store_paths(location).backups;
+    let backup = dir.join(format!("combos-{}.yaml", Local::now().format("%Y%m%d-%H%M%S%.3f")));
+    match layout(location) {
+        Layout::File => {
+            let path = store_paths(location).file;
+            if !path.exists() {
+                return Ok(());
+            }
+            create_dir_all(&dir).map_err(|e| write_error(&dir, e))?;
+            fs::copy(&path, &backup).map_err(|e| write_error(&backup, e))?;
+        }
+        Layout::Directory | Layout::Sqlite => {
+            let Ok(doc) = storage(location).load() else {
+                return Ok(());
+            };
+            create_dir_all(&dir).map_err(|e| write_error(&dir, e))?;
+            let text = serde_yaml::to_string(&doc).expect("Failed to serialize combos");
+            fs::write(&backup, text).map_err(|e| write_error(&backup, e))?;
+        }
+    }
+    let backups = list_backups(location);
+    for old in &backups[..backups.len().saturating_sub(MAX_BACKUPS)] {
+        let _ = fs::remove_file(old);
+    }
+    Ok(())
+}
+
+/// Backups of the combo file, oldest first.
+pub fn list_backups(location: &Location) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(store_paths(location).backups) else {
+        return vec![];
+    };
+    let mut backups: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "yaml"))
+        .collect();
+    // Names embed the time they were taken, so they sort chronologically.
+    backups.sort();
+    backups
+}
+
+/// Replace the combo file with the backup called `name`, or the newest one.
+/// The file being replaced is backed up first, even when it cannot be
+/// parsed, so a restore can itself be rolled back. Returns the restored
+/// backup and its combos.
+pub fn restore_backup(
+    location: &Location,
+    name: Option<&str>,
+) -> Result<(PathBuf, Vec<Combo>), StoreError> {
+    let _lock = lock_store(location);
+    let backups = list_backups(location);
+    let backup = match name {
+        Some(name) => backups
+            .into_iter()
+            .find(|p| p.file_name().is_some_and(|f| f == name))
+            .ok_or_else(|| StoreError::NoBackup(name.to_string()))?,
+        None => backups.into_iter().last().ok_or_else(|| StoreError::NoBackup("latest".into()))?,
+    };
+    let doc = read_document(&backup)?;
+    backup_combos(location)?;
+    storage(location).save(&doc)?;
+    let backup_name = backup.file_name().unwrap_or_default().to_string_lossy();
+    commit_change(location, &format!("restore {backup_name}"));
+    Ok((backup, doc.combos))
+}
+
+/// Write `contents` to a temporary file next to `path`, flush it to disk and
+/// rename it into place, so the file is never seen half-written.
+fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let dir = path.parent().expect("Combo file has no parent directory");
+    let mut tmp = NamedTempFile::new_in(dir)?;
+    tmp.write_all(contents)?;
+    tmp.as_file().sync_all()?;
+    tmp.persist(path).map_err(|e| e.error)?;
+    // Make the rename itself durable.
+    if let Ok(d) = File::open(dir) {
+        let _ = d.sync_all();
+    }
+    Ok(())
+}
+
+fn write_error(path: &Path, source: io::Error) -> StoreError {
+    StoreError::Write { path: path.to_path_buf(), source }
+}
+
+/// Take an exclusive advisory lock on the combo store, held until the
+/// returned file is dropped.
+fn lock_store(location: &Location) -> File {
+    let path = store_paths(location).lock;
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .expect("Cannot open combo lock file");
+    lock.lock().expect("Cannot lock combo file");
+    lock
+}
+
+/// Apply `change`, described by `description` (e.g. "delete 'build'"), to
+/// the combos of the library at `location` under its lock. The combos are
+/// re-read first, so changes saved by another comboman in the meantime are
+/// kept. The previous file is backed up, and nothing is written if it cannot
+/// be parsed or `change` leaves the combos as they were. Unless only usage
+/// data changed, the change can be undone with `undo_last_change`.
+pub fn modify_combos<R>(
+    location: &Location,
+    description: &str,
+    change: impl FnOnce(&mut Vec<Combo>) -> R,
+) -> Result<R, StoreError> {
+    modify_library(location, description, |combos, _| change(combos))
+}
+
+/// Like `modify_combos`, but `change` also gets the trash to move combos in
+/// and out of. The trash is only written once the combos are saved, so the
+/// two never disagree.
+fn modify_library<R>(
+    location: &Location,
+    description: &str,
+    change: impl FnOnce(&mut Vec<Combo>, &mut Vec<TrashedCombo>) -> R,
+) -> Result<R, StoreError> {
+    let _lock = lock_store(location);
+    let storage = storage(location);
+    let mut doc = storage.load()?;
+    let mut trash = read_trash(location);
+    let before = doc.combos.clone();
+    let result = change(&mut doc.combos, &mut trash);
+    let serialize =
+        |combos: &[Combo]| serde_yaml::to_value(combos).expect("Failed to serialize combos");
+    if serialize(&before) == serialize(&doc.combos) {
+        return Ok(result);
+    }
+    backup_combos(location)?;
+    doc.version = doc.version.max(CURRENT_VERSION);
+    storage.save(&doc)?;
+    write_trash(location, &trash)?;
+    record_change(location, description, &before, &doc.combos)?;
+    Ok(result)
+}
+
+/// A combo without its usage data, to tell real changes from runs.
+fn definition(combo: &Combo) -> Value {
+    let mut value = serde_yaml::to_value(combo).expect("Failed to serialize combo");
+    if let Value::Mapping(fields) = &mut value {
+        for field in VOLATILE_FIELDS {
+            fields.remove(*field);
+        }
+    }
+    value
+}
+
+/// How to undo the change from `before` to `after`, or None when no combo's
+/// definition changed.
+fn undo_record(description: &str, before: &[Combo], after: &[Combo]) -> Option<UndoRecord> {
+    let changed = |combo: &Combo, others: &[Combo]| {
+        others
+            .iter()
+            .find(|c| c.name == combo.name)
+            .is_none_or(|c| definition(c) != definition(combo))
+    };
+    let removed: Vec<Combo> = before.iter().filter(|c| changed(c, after)).cloned().collect();
+    let added: Vec<String> =
+        after.iter().filter(|c| changed(c, before)).map(|c| c.name.clone()).collect();
+    if removed.is_empty() && added.is_empty() {
+        return None;
+    }
+    Some(UndoRecord {
+        description: description.to_string(),
+        at: Utc::now().timestamp(),
+        before: removed,
+        added,
+    })
+}
+
+/// Remember how to undo the change from `before` to `after`, and commit it
+/// if the library is kept in git, unless no combo's definition changed.
+fn record_change(
+    location: &Location,
+    description: &str,
+    before: &[Combo],
+    after: &[Combo],
+) -> Result<(), StoreError> {
+    if let Some(record) = undo_record(description, before, after) {
+        let text = serde_yaml::to_string(&record).expect("Failed to serialize undo record");
+        let path = store_paths(location).undo;
+        write_atomic(&path, text.as_bytes()).map_err(|e| write_error(&path, e))?;
+        commit_change(location, description);
+    }
+    Ok(())
+}
+
+/// Description and time of the last change to the library at `location`
+/// that can be undone.
+pub fn last_change(location: &Location) -> Option<(String, i64)> {
+    let text = fs::read_to_string(store_paths(location).undo).ok()?;
+    let record: UndoRecord = serde_yaml::from_str(&text).ok()?;
+    Some((record.description, record.at))
+}
+
+/// Undo the last change to the library at `location` and return its
+/// description. Undoing is itself a change, so undoing twice redoes it.
+pub fn undo_last_change(location: &Location) -> Result<Option<String>, StoreError> {
+    let path = store_paths(location).undo;
+    let Ok(text) = fs::read_to_string(&path) else {
+        return Ok(None);
+    };
+    let record: UndoRecord = serde_yaml::from_str(&text).map_err(|e| parse_error(&path, e))?;
+    let description = format!("undo {}", record.description);
+    let restored: Vec<String> = record.before.iter().map(|c| c.name.clone()).collect();
+    // Undoing a restore puts the combo back in the trash; anything else the
+    // change added is simply removed again.
+    let from_trash = record.description.ends_with("' from the trash");
+    let renamed = record.before.len() == 1
+        && record.added.len() == 1
+        && record.before[0].name != record.added[0];
+    modify_library(location, &description, |combos, trash| {
+        // A deleted combo is back, so it no longer belongs in the trash.
+        trash.retain(|t| !(restored.contains(&t.combo.name) && t.deleted_at >= record.at));
+        let now = Utc::now().timestamp();
+        let mut before = record.before;
+        let mut kept = Vec::with_capacity(combos.len());
+        for current in combos.drain(..) {
+            let previous = before.iter().position(|c| c.name == current.name).or_else(|| {
+                (renamed && !before.is_empty() && current.name == record.added[0]).then_some(0)
+            });
+            match previous {
+                Some(i) => {
+                    // Runs since the change still count.
+                    let mut combo = before.remove(i);
+                    combo.last_used = current.last_used;
+                    combo.use_count = current.use_count;
+                    combo.total_runtime = current.total_runtime;
+                    kept.push(combo);
+                }
+                None if record.added.contains(&current.name) => {
+                    if from_trash {
+                        trash.push(TrashedCombo { deleted_at: now, combo: current });
+                    }
+                }
+                None => kept.push(current),
+            }
+        }
+        kept.extend(before);
+        *combos = kept;
+    })?;
+    Ok(Some(record.description))
+}
+
+fn read_trash(location: &Location) -> Vec<TrashedCombo> {
+    fs::read_to_string(store_paths(location).trash)
+        .ok()
+        .and_then(|text| serde_yaml::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn write_trash(location: &Location, trash: &[TrashedCombo]) -> Result<(), StoreError> {
+    let text = serde_yaml::to_string(trash).expect("Failed to serialize trash");
+    let path = store_paths(location).trash;
+    write_atomic(&path, text.as_bytes()).map_err(|e| write_error(&path, e))
+}
+
+/// Move the combo `name` from the library at `location` to its trash.
+/// Returns whether there was such a combo.
+pub fn trash_combo(location: &Location, name: &str) -> Result<bool, StoreError> {
+    modify_library(location, &format!("delete '{name}'"), |combos, trash| {
+        let Some(i) = combos.iter().position(|c| c.name == name) else {
+            return false;
+        };
+        trash.push(TrashedCombo { deleted_at: Utc::now().timestamp(), combo: combos.remove(i) });
+        true
+    })
+}
+
+/// The trash of the library at `location`, oldest first, after removing
+/// combos deleted more than `retention_days` ago.
+pub fn list_trash(location: &Location, retention_days: i64) -> Vec<TrashedCombo> {
+    let _lock = lock_store(location);
+    let mut trash = read_trash(location);
+    let cutoff = Utc::now().timestamp() - retention_days * 24 * 60 * 60;
+    let before = trash.len();
+    trash.retain(|t| t.deleted_at >= cutoff);
+    if trash.len() < before {
+        // Purging can wait for the next listing if this fails.
+        let _ = write_trash(location, &trash);
+    }
+    trash
+}
+
+/// Put the most recently deleted combo called `name` back into the library
+/// at `location`. Returns Ok(false) if the trash has no such combo; a combo
+/// of the same name in the library is an error.
+pub fn restore_from_trash(location: &Location, name: &str) -> Result<Result<bool, String>, StoreError> {
+    modify_library(location, &format!("restore '{name}' from the trash"), |combos, trash| {
+        let Some(i) = trash.iter().rposition(|t| t.combo.name == name) else {
+            return Ok(false);
+        };
+        if combos.iter().any(|c| c.name == name) {
+            return Err(format!("a combo named '{name}' already exists"));
+        }
+        combos.push(trash.remove(i).combo);
+        Ok(true)
+    })
+}
+
+/// Record that the combo `name` in the library at `location` was just run
+/// and took `runtime`. Returns whether there is such a combo.
+pub fn record_use(location: &Location, name: &str, runtime: Duration) -> Result<bool, StoreError> {
+    let _lock = lock_store(location);
+    storage(location).record_use(name, runtime)
+}
+
+/// Replace the commands of `combo`, keeping the old ones as a revision.
+pub fn set_commands(combo: &mut Combo, commands: Vec<String>) {
+    if commands == combo.commands {
+        return;
+    }
+    let old = std::mem::replace(&mut combo.commands, commands);
+    combo.revisions.push(Revision {
+        commands: old,
+        changed_at: Utc::now().timestamp(),
+        author: author(),
+    });
+}
+
+/// Name to record on revisions: git's `user.name`, or the login name.
+fn author() -> Option<String> {
+    std::process::Command::new("git")
+        .args(["config", "user.name"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .and_then(|o| String::from_utf8(o.stdout).ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .or_else(|| std::env::var("USER").ok())
+        .or_else(|| std::env::var("USERNAME").ok())
+}
+
+/// Add `combo` to the library at `location` and return its name. If the name
+/// is empty, generate fallback using sanitize_name(last_command) + _i to
+/// avoid collisions; a name that is taken gets a `_i` suffix too. The other
+/// combos are only read when the name has to be generated.
+pub fn add_combo(mut combo: Combo, location: &Location) -> Result<String, StoreError> {
+    let _lock = lock_store(location);
+    let storage = storage(location);
+    let now = Utc::now();
+    combo.created_at = Some(now.timestamp());
+    combo.last_used = now.timestamp();
+    // An add leaves the other combos alone and can be undone, so backing up
+    // a whole database for it isn't worth it.
+    if layout(location) != Layout::Sqlite {
+        backup_combos(location)?;
+    }
+    if combo.name.is_empty() || !storage.add(&combo)? {
+        let combos = storage.load()?.combos;
+        let base = if combo.name.is_empty() {
+            combo.commands.last().map(|s| sanitize_name(s)).unwrap_or_else(|| "combo".to_string())
+        } else {
+            combo.name.clone()
+        };
+        combo.name = unique_name(&combos, &base);
+        storage.add(&combo)?;
+    }
+    let description = format!("add '{}'", combo.name);
+    record_change(location, &description, &[], std::slice::from_ref(&combo))?;
+    Ok(combo.name)
+}
+
+/// The first of `base_0`, `base_1`, ... that no combo in `combos` is called.
+pub fn unique_name(combos: &[Combo], base: &str) -> String {
+    let mut i = 0;
+    loop {
+        let candidate = format!("{base}_{i}");
+        if !combos.iter().any(|c| c.name == candidate) {
+            break candidate;
+        }
+        i += 1;
+    }
+}
+
+/// Sanitize a command token into a safe base name.
+fn sanitize_name(cmd: &str) -> String {
+    let first_token = cmd.split_whitespace().next().unwrap_or("combo");
+    first_token
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>()
+        .to_lowercase()
+        .chars()
+        .take(64)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A global library in a fresh directory, with the combos `names`.
+    fn library(names: &[&str]) -> (TempDir, Location) {
+        let dir = tempfile::tempdir().unwrap();
+        let location = Location::Global(Some(dir.path().to_string_lossy().into_owned()));
+        for name in names {
+            let combo = Combo {
+                name: name.to_string(),
+                commands: vec!["ls".to_string()],
+                ..Default::default()
+            };
+            add_combo(combo, &location).unwrap();
+        }
+        (dir, location)
+    }
+
+    fn names(location: &Location) -> Vec<String> {
+        load_combos(location).unwrap().into_iter().map(|c| c.name).collect()
+    }
+
+    fn trashed(location: &Location) -> Vec<String> {
+        list_trash(location, 30).into_iter().map(|t| t.combo.name).collect()
+    }
+
+    fn rename(location: &Location, from: &str, to: &str) {
+        modify_combos(location, &format!("rename '{from}' to '{to}'"), |combos| {
+            combos.iter_mut().find(|c| c.name == from).unwrap().name = to.to_string();
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn undoing_an_add_removes_the_combo() {
+        let (_dir, location) = library(&["a", "b"]);
+        assert_eq!(undo_last_change(&location).unwrap().as_deref(), Some("add 'b'"));
+        assert_eq!(names(&location), ["a"]);
+        assert!(trashed(&location).is_empty());
+    }
+
+    #[test]
+    fn undoing_a_delete_takes_the_combo_out_of_the_trash() {
+        let (_dir, location) = library(&["a", "b"]);
+        assert!(trash_combo(&location, "a").unwrap());
+        assert_eq!(trashed(&location), ["a"]);
+        undo_last_change(&location).unwrap();
+        assert_eq!(names(&location), ["b", "a"]);
+        assert!(trashed(&location).is_empty());
+    }
+
+    #[test]
+    fn undoing_an_edit_keeps_the_position_and_later_runs() {
+        let (_dir, location) = library(&["a", "b"]);
+        record_use(&location, "a", Duration::from_secs(1)).unwrap();
+        modify_combos(&location, "edit 'a'", |combos| {
+            set_commands(&mut combos[0], vec!["ls -l".to_string()]);
+        })
+        .unwrap();
+        record_use(&location, "a", Duration::from_secs(1)).unwrap();
+        undo_last_change(&location).unwrap();
+        let combos = load_combos(&location).unwrap();
+        assert_eq!(combos[0].name, "a");
+        assert_eq!(combos[0].commands, ["ls"]);
+        assert_eq!(combos[0].use_count, 2);
+        assert!(trashed(&location).is_empty());
+    }
+
+    #[test]
+    fn undoing_a_rename_brings_the_old_name_back() {
+        let (_dir, location) = library(&["a", "b"]);
+        rename(&location, "a", "c");
+        record_use(&location, "c", Duration::from_secs(1)).unwrap();
+        undo_last_change(&location).unwrap();
+        assert_eq!(names(&location), ["a", "b"]);
+        assert_eq!(load_combos(&location).unwrap()[0].use_count, 1);
+        assert!(trashed(&location).is_empty());
+    }
+
+    #[test]
+    fn undoing_a_restore_puts_the_combo_back_in_the_trash() {
+        let (_dir, location) = library(&["a"]);
+        trash_combo(&location, "a").unwrap();
+        assert_eq!(restore_from_trash(&location, "a").unwrap(), Ok(true));
+        assert!(trashed(&location).is_empty());
+        undo_last_change(&location).unwrap();
+        assert!(names(&location).is_empty());
+        assert_eq!(trashed(&location), ["a"]);
+    }
+}
@@ -0,0 +1,201 @@
+//! The SQLite layout, for libraries too large to rewrite on every run.
+
+use super::{definition, write_error, ComboFile, Storage, StoreError, CURRENT_VERSION};
+use crate::types::Combo;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Params, Transaction};
+use serde_yaml::Mapping;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Usage data gets its own columns so a run is a single `UPDATE`; the rest of
+/// a combo is kept as YAML, so fields added later need no migration. Tags are
+/// also listed in `tags` to look combos up by tag.
+const SCHEMA: &str = "
+    PRAGMA foreign_keys = ON;
+    CREATE TABLE IF NOT EXISTS combos (
+        name TEXT PRIMARY KEY,
+        position INTEGER NOT NULL,
+        definition TEXT NOT NULL,
+        last_used INTEGER NOT NULL DEFAULT 0,
+        use_count INTEGER NOT NULL DEFAULT 0,
+        total_runtime REAL NOT NULL DEFAULT 0
+    );
+    CREATE TABLE IF NOT EXISTS tags (
+        combo TEXT NOT NULL REFERENCES combos (name) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (combo, tag)
+    );
+    CREATE INDEX IF NOT EXISTS tags_tag ON tags (tag);
+    CREATE TABLE IF NOT EXISTS library (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+";
+
+/// All combos in `combos.db`.
+pub struct SqliteStorage {
+    pub path: PathBuf,
+}
+
+impl SqliteStorage {
+    fn open(&self) -> Result<Connection, StoreError> {
+        let conn = Connection::open(&self.path).map_err(|e| self.error(e))?;
+        conn.busy_timeout(Duration::from_secs(5)).map_err(|e| self.error(e))?;
+        conn.execute_batch(SCHEMA).map_err(|e| self.error(e))?;
+        Ok(conn)
+    }
+
+    fn error(&self, source: rusqlite::Error) -> StoreError {
+        StoreError::Database { path: self.path.clone(), source }
+    }
+
+    /// The combos for which the SQL condition `filter` holds, in order.
+    fn select(
+        &self,
+        conn: &Connection,
+        filter: &str,
+        params: impl Params,
+    ) -> Result<Vec<Combo>, StoreError> {
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT name, definition, last_used, use_count, total_runtime
+                 FROM combos WHERE {filter} ORDER BY position"
+            ))
+            .map_err(|e| self.error(e))?;
+        let rows = stmt
+            .query_map(params, |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, f64>(4)?,
+                ))
+            })
+            .map_err(|e| self.error(e))?;
+        let mut combos = Vec::new();
+        for row in rows {
+            let (name, definition, last_used, use_count, total_runtime) =
+                row.map_err(|e| self.error(e))?;
+            let mut combo: Combo =
+                serde_yaml::from_str(&definition).map_err(|e| self.parse_error(&name, e))?;
+            combo.last_used = last_used;
+            combo.use_count = use_count as u64;
+            combo.total_runtime = total_runtime;
+            combos.push(combo);
+        }
+        Ok(combos)
+    }
+
+    fn parse_error(&self, name: &str, e: serde_yaml::Error) -> StoreError {
+        StoreError::Parse {
+            path: self.path.clone(),
+            line: None,
+            column: None,
+            message: format!("combo '{name}': {e}"),
+        }
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn load(&self) -> Result<ComboFile, StoreError> {
+        let conn = self.open()?;
+        let combos = self.select(&conn, "1", [])?;
+        let extra: Option<String> = conn
+            .query_row("SELECT value FROM library WHERE key = 'extra'", [], |row| row.get(0))
+            .optional()
+            .map_err(|e| self.error(e))?;
+        let extra = match extra {
+            Some(text) => serde_yaml::from_str(&text).map_err(|e| self.parse_error("", e))?,
+            None => Mapping::new(),
+        };
+        Ok(ComboFile { version: CURRENT_VERSION, combos, extra })
+    }
+
+    fn save(&self, doc: &ComboFile) -> Result<(), StoreError> {
+        let mut conn = self.open()?;
+        let tx = conn.transaction().map_err(|e| self.error(e))?;
+        tx.execute("DELETE FROM combos", []).map_err(|e| self.error(e))?;
+        for (position, combo) in doc.combos.iter().enumerate() {
+            insert(&tx, combo, position as i64).map_err(|e| self.error(e))?;
+        }
+        let extra = serde_yaml::to_string(&doc.extra).expect("Failed to serialize combos");
+        tx.execute(
+            "INSERT OR REPLACE INTO library (key, value) VALUES ('extra', ?1)",
+            params![extra],
+        )
+        .map_err(|e| self.error(e))?;
+        tx.commit().map_err(|e| self.error(e))
+    }
+
+    fn remove(&self) -> Result<(), StoreError> {
+        fs::remove_file(&self.path).map_err(|e| write_error(&self.path, e))
+    }
+
+    fn find(&self, name: &str) -> Result<Option<Combo>, StoreError> {
+        let conn = self.open()?;
+        Ok(self.select(&conn, "name = ?1", params![name])?.pop())
+    }
+
+    fn tagged(&self, tag: &str) -> Result<Vec<Combo>, StoreError> {
+        let conn = self.open()?;
+        self.select(&conn, "name IN (SELECT combo FROM tags WHERE tag = ?1)", params![tag])
+    }
+
+    fn add(&self, combo: &Combo) -> Result<bool, StoreError> {
+        let mut conn = self.open()?;
+        let tx = conn.transaction().map_err(|e| self.error(e))?;
+        let taken: bool = tx
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM combos WHERE name = ?1)",
+                params![combo.name],
+                |row| row.get(0),
+            )
+            .map_err(|e| self.error(e))?;
+        if taken {
+            return Ok(false);
+        }
+        let position: i64 = tx
+            .query_row("SELECT COALESCE(MAX(position) + 1, 0) FROM combos", [], |row| row.get(0))
+            .map_err(|e| self.error(e))?;
+        insert(&tx, combo, position).map_err(|e| self.error(e))?;
+        tx.commit().map_err(|e| self.error(e))?;
+        Ok(true)
+    }
+
+    fn record_use(&self, name: &str, runtime: Duration) -> Result<bool, StoreError> {
+        let conn = self.open()?;
+        let updated = conn
+            .execute(
+                "UPDATE combos SET last_used = ?1, use_count = use_count + 1,
+                 total_runtime = total_runtime + ?2 WHERE name = ?3",
+                params![Utc::now().timestamp(), runtime.as_secs_f64(), name],
+            )
+            .map_err(|e| self.error(e))?;
+        Ok(updated > 0)
+    }
+}
+
+/// Insert `combo` at `position`, with its tags.
+fn insert(tx: &Transaction, combo: &Combo, position: i64) -> rusqlite::Result<()> {
+    let definition =
+        serde_yaml::to_string(&definition(combo)).expect("Failed to serialize combo");
+    tx.execute(
+        "INSERT INTO combos (name, position, definition, last_used, use_count, total_runtime)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            combo.name,
+            position,
+            definition,
+            combo.last_used,
+            combo.use_count as i64,
+            combo.total_runtime
+        ],
+    )?;
+    for tag in &combo.tags {
+        tx.execute(
+            "INSERT OR IGNORE INTO tags (combo, tag) VALUES (?1, ?2)",
+            params![combo.name, tag],
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,264 @@
+//! The YAML layouts: a single combo file, or one file per combo.
+
+use super::{
+    combo_file_paths, parse_error, read_document, write_atomic, write_error, ComboFile, Storage,
+    StoreError, CURRENT_VERSION, VOLATILE_FIELDS,
+};
+use crate::types::Combo;
+use chrono::Utc;
+use serde_yaml::{Mapping, Value};
+use std::fs::{self, create_dir_all};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// All combos in one `combos.yaml`.
+pub struct FileStorage {
+    pub path: PathBuf,
+    /// State file to keep the volatile fields in instead, so that running a
+    /// combo doesn't change a file shared with others.
+    pub state: Option<PathBuf>,
+}
+
+impl Storage for FileStorage {
+    fn load(&self) -> Result<ComboFile, StoreError> {
+        if !self.path.exists() {
+            return Ok(ComboFile { version: CURRENT_VERSION, ..Default::default() });
+        }
+        let mut doc = read_document(&self.path)?;
+        if let Some(state_path) = &self.state {
+            let state = read_state(state_path)?;
+            for combo in &mut doc.combos {
+                let Some(Value::Mapping(usage)) = state.get(combo.name.as_str()) else {
+                    continue;
+                };
+                let get = |field: &str| usage.get(field).cloned().unwrap_or_default();
+                combo.last_used = serde_yaml::from_value(get("last_used")).unwrap_or_default();
+                combo.use_count = serde_yaml::from_value(get("use_count")).unwrap_or_default();
+                combo.total_runtime =
+                    serde_yaml::from_value(get("total_runtime")).unwrap_or_default();
+            }
+        }
+        Ok(doc)
+    }
+
+    fn save(&self, doc: &ComboFile) -> Result<(), StoreError> {
+        let mut value = serde_yaml::to_value(doc).expect("Failed to serialize combos");
+        if let Some(state_path) = &self.state {
+            let mut state = Mapping::new();
+            if let Some(Value::Sequence(combos)) = value.get_mut("combos") {
+                for combo in combos.iter_mut().filter_map(Value::as_mapping_mut) {
+                    split_usage(combo, &mut state);
+                }
+            }
+            write_state(state_path, &state)?;
+        }
+        let text = serde_yaml::to_string(&value).expect("Failed to serialize combos");
+        write_atomic(&self.path, text.as_bytes()).map_err(|e| write_error(&self.path, e))
+    }
+
+    fn record_use(&self, name: &str, runtime: Duration) -> Result<bool, StoreError> {
+        let mut doc = self.load()?;
+        if let Some(state) = &self.state {
+            return record_use_in_state(&doc.combos, state, name, runtime);
+        }
+        let Some(c) = doc.combos.iter_mut().find(|c| c.name == name) else {
+            return Ok(false);
+        };
+        c.last_used = Utc::now().timestamp();
+        c.use_count += 1;
+        c.total_runtime += runtime.as_secs_f64();
+        self.save(&doc)?;
+        Ok(true)
+    }
+
+    fn remove(&self) -> Result<(), StoreError> {
+        if self.path.exists() {
+            fs::remove_file(&self.path).map_err(|e| write_error(&self.path, e))?;
+        }
+        Ok(())
+    }
+}
+
+/// One YAML file per combo in a `combos` directory, with the volatile fields
+/// of all combos in a state file, usually `state.yaml` next to it.
+pub struct DirectoryStorage {
+    pub dir: PathBuf,
+    pub state: PathBuf,
+}
+
+impl Storage for DirectoryStorage {
+    fn load(&self) -> Result<ComboFile, StoreError> {
+        read_directory(&self.dir, &self.state)
+    }
+
+    fn save(&self, doc: &ComboFile) -> Result<(), StoreError> {
+        write_directory(&self.dir, &self.state, doc)
+    }
+
+    fn record_use(&self, name: &str, runtime: Duration) -> Result<bool, StoreError> {
+        record_use_in_state(&self.load()?.combos, &self.state, name, runtime)
+    }
+
+    fn remove(&self) -> Result<(), StoreError> {
+        if self.dir.is_dir() {
+            fs::remove_dir_all(&self.dir).map_err(|e| write_error(&self.dir, e))?;
+        }
+        let _ = fs::remove_file(&self.state);
+        Ok(())
+    }
+}
+
+/// Usage data of each combo, by name, from the state file at `path`.
+fn read_state(path: &Path) -> Result<Mapping, StoreError> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(serde_yaml::from_str::<Option<Mapping>>(&text)
+            .map_err(|e| parse_error(path, e))?
+            .unwrap_or_default()),
+        Err(_) => Ok(Mapping::new()),
+    }
+}
+
+fn write_state(path: &Path, state: &Mapping) -> Result<(), StoreError> {
+    let text = serde_yaml::to_string(state).expect("Failed to serialize combo state");
+    write_atomic(path, text.as_bytes()).map_err(|e| write_error(path, e))
+}
+
+/// Record that the combo `name` among `combos` was just run, in the state
+/// file at `path` alone, so that the combo files stay exactly as written.
+fn record_use_in_state(
+    combos: &[Combo],
+    path: &Path,
+    name: &str,
+    runtime: Duration,
+) -> Result<bool, StoreError> {
+    let Some(combo) = combos.iter().find(|c| c.name == name) else {
+        return Ok(false);
+    };
+    let mut usage = Mapping::new();
+    usage.insert("last_used".into(), Utc::now().timestamp().into());
+    usage.insert("use_count".into(), (combo.use_count + 1).into());
+    usage.insert("total_runtime".into(), (combo.total_runtime + runtime.as_secs_f64()).into());
+    let mut state = read_state(path)?;
+    state.insert(name.into(), Value::Mapping(usage));
+    write_state(path, &state)?;
+    Ok(true)
+}
+
+/// Move the volatile fields out of the serialized `combo` into its entry in
+/// `state`.
+fn split_usage(combo: &mut Mapping, state: &mut Mapping) {
+    let mut usage = Mapping::new();
+    for field in VOLATILE_FIELDS {
+        if let Some(value) = combo.remove(*field) {
+            usage.insert(Value::from(*field), value);
+        }
+    }
+    let name = combo.get("name").cloned().unwrap_or_default();
+    state.insert(name, Value::Mapping(usage));
+}
+
+/// Read the combos of the directory layout, one per `*.yaml` file in `dir`,
+/// adding their volatile fields from the state file.
+fn read_directory(dir: &Path, state_path: &Path) -> Result<ComboFile, StoreError> {
+    let state = read_state(state_path)?;
+    let entries =
+        fs::read_dir(dir).map_err(|source| StoreError::Io { path: dir.to_path_buf(), source })?;
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "yaml"))
+        .collect();
+    paths.sort();
+    let mut combos = Vec::with_capacity(paths.len());
+    for path in paths {
+        let text = fs::read_to_string(&path)
+            .map_err(|source| StoreError::Io { path: path.clone(), source })?;
+        let mut fields: Mapping = serde_yaml::from_str(&text).map_err(|e| parse_error(&path, e))?;
+        let name = fields.get("name").cloned().unwrap_or_default();
+        if let Some(Value::Mapping(usage)) = state.get(&name) {
+            for field in VOLATILE_FIELDS {
+                if let Some(value) = usage.get(*field) {
+                    fields.insert(Value::from(*field), value.clone());
+                }
+            }
+        }
+        combos.push(serde_yaml::from_value(Value::Mapping(fields)).map_err(|e| parse_error(&path, e))?);
+    }
+    Ok(ComboFile { version: CURRENT_VERSION, combos, ..Default::default() })
+}
+
+/// Write `doc` in the directory layout. Combo files whose contents did not
+/// change are left untouched, and files of combos no longer in `doc` are
+/// removed.
+fn write_directory(dir: &Path, state_path: &Path, doc: &ComboFile) -> Result<(), StoreError> {
+    create_dir_all(dir).map_err(|e| write_error(dir, e))?;
+    let written = combo_file_paths(dir, &doc.combos)?;
+    let mut state = Mapping::new();
+    for (combo, path) in doc.combos.iter().zip(&written) {
+        let Value::Mapping(mut fields) =
+            serde_yaml::to_value(combo).expect("Failed to serialize combo")
+        else {
+            unreachable!("combos serialize to mappings");
+        };
+        split_usage(&mut fields, &mut state);
+        let text = serde_yaml::to_string(&fields).expect("Failed to serialize combo");
+        if fs::read_to_string(path).ok().as_deref() != Some(text.as_str()) {
+            write_atomic(path, text.as_bytes()).map_err(|e| write_error(path, e))?;
+        }
+    }
+    if let Ok(entries) = fs::read_dir(dir) {
+        for path in entries.filter_map(|e| e.ok().map(|e| e.path())) {
+            if path.extension().is_some_and(|ext| ext == "yaml") && !written.contains(&path) {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+    write_state(state_path, &state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn running_a_project_combo_leaves_its_file_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".comboman.yaml");
+        let text = "# Shared with the team\n- name: build\n  commands: [make]\n";
+        fs::write(&path, text).unwrap();
+        let state = Some(dir.path().join("state.yaml"));
+        let storage = FileStorage { path: path.clone(), state };
+        assert!(storage.record_use("build", Duration::from_secs(2)).unwrap());
+        assert!(storage.record_use("build", Duration::from_secs(1)).unwrap());
+        assert!(!storage.record_use("test", Duration::ZERO).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+        let combo = &storage.load().unwrap().combos[0];
+        assert_eq!(combo.use_count, 2);
+        assert_eq!(combo.total_runtime, 3.0);
+    }
+
+    #[test]
+    fn running_a_combo_leaves_the_combo_files_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let combos = dir.path().join("combos");
+        fs::create_dir(&combos).unwrap();
+        let text = "# Deploys to staging\nname: deploy\ncommands: [make, make deploy]\n";
+        fs::write(combos.join("deploy.yaml"), text).unwrap();
+        let storage = DirectoryStorage { dir: combos.clone(), state: dir.path().join("state.yaml") };
+        assert!(storage.record_use("deploy", Duration::from_secs(1)).unwrap());
+        assert_eq!(fs::read_to_string(combos.join("deploy.yaml")).unwrap(), text);
+        assert_eq!(storage.load().unwrap().combos[0].use_count, 1);
+    }
+
+    #[test]
+    fn names_differing_only_in_case_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let combos = dir.path().join("combos");
+        let storage = DirectoryStorage { dir: combos.clone(), state: dir.path().join("state.yaml") };
+        let combo = |name: &str| Combo { name: name.to_string(), ..Default::default() };
+        let clashing = vec![combo("Build"), combo("build")];
+        let doc = ComboFile { version: CURRENT_VERSION, combos: clashing, ..Default::default() };
+        let err = storage.save(&doc).unwrap_err();
+        assert!(err.to_string().contains("'Build' and 'build' differ only in case"), "{err}");
+        assert_eq!(fs::read_dir(&combos).unwrap().count(), 0);
+    }
+}
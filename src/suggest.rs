@@ -0,0 +1,116 @@
+use crate::types::HistoryEntry;
+use std::collections::HashMap;
+
+/// Shortest and longest command sequences considered for suggestions.
+const MIN_LEN: usize = 2;
+const MAX_LEN: usize = 6;
+
+/// Programs whose second word is an argument rather than a subcommand.
+const TAKES_ARGS: &[&str] = &[
+    "cd", "pushd", "echo", "cat", "less", "more", "vi", "vim", "nvim", "nano", "emacs", "code",
+    "ls", "rm", "mkdir", "rmdir", "touch", "cp", "mv", "man", "which", "source", ".", "open",
+];
+
+/// A sequence of commands that recurs in the history.
+pub struct Suggestion {
+    /// The normalized form of each command, e.g. `git commit`.
+    pub shape: Vec<String>,
+    /// Number of non-overlapping occurrences.
+    pub count: usize,
+    /// History indices of the most recent occurrence.
+    pub latest: Vec<usize>,
+}
+
+impl Suggestion {
+    fn score(&self) -> usize {
+        self.count * self.shape.len()
+    }
+}
+
+/// Reduce a command to its program and subcommand so that runs of the same
+/// steps with different arguments match: `sudo git commit -m x` becomes
+/// `git commit`, `cd ~/src` becomes `cd`.
+fn command_shape(command: &str) -> String {
+    let mut words = command
+        .split_whitespace()
+        .skip_while(|w| *w == "sudo" || (w.contains('=') && !w.starts_with('-')));
+    let Some(program) = words.next() else {
+        return String::new();
+    };
+    let program = program.rsplit('/').next().unwrap_or(program);
+    match words.next() {
+        Some(sub) if !TAKES_ARGS.contains(&program) && is_subcommand(sub) => {
+            format!("{program} {sub}")
+        }
+        _ => program.to_string(),
+    }
+}
+
+/// Whether `word` looks like a subcommand (`commit`, `build-std`) rather
+/// than a path, flag or value.
+fn is_subcommand(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_ascii_lowercase())
+        && word
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// Find command sequences that recur in `history` (oldest first), best first.
+/// Sequences that only ever occur inside a longer suggestion are dropped.
+pub fn suggest(history: &[HistoryEntry], max: usize) -> Vec<Suggestion> {
+    let shapes: Vec<String> = history.iter().map(|e| command_shape(&e.command)).collect();
+
+    let mut candidates: Vec<Suggestion> = Vec::new();
+    for len in MIN_LEN..=MAX_LEN.min(shapes.len()) {
+        // Start positions of each window, in order.
+        let mut starts: HashMap<&[String], Vec<usize>> = HashMap::new();
+        for (i, window) in shapes.windows(len).enumerate() {
+            if window.iter().any(|s| s.is_empty()) || window.iter().all(|s| s == &window[0]) {
+                continue;
+            }
+            starts.entry(window).or_default().push(i);
+        }
+        for (window, positions) in starts {
+            // Count occurrences that don't overlap an earlier one.
+            let mut count = 0;
+            let mut next_free = 0;
+            for &p in &positions {
+                if p >= next_free {
+                    count += 1;
+                    next_free = p + len;
+                }
+            }
+            if count < 2 {
+                continue;
+            }
+            let last = *positions.last().unwrap();
+            candidates.push(Suggestion {
+                shape: window.to_vec(),
+                count,
+                latest: (last..last + len).collect(),
+            });
+        }
+    }
+
+    candidates.sort_by(|a, b| {
+        b.score()
+            .cmp(&a.score())
+            .then(b.latest.last().cmp(&a.latest.last()))
+    });
+    let mut chosen: Vec<Suggestion> = Vec::new();
+    for candidate in candidates {
+        let subsumed = chosen.iter().any(|c| {
+            c.count >= candidate.count
+                && c.shape
+                    .windows(candidate.shape.len())
+                    .any(|w| w == candidate.shape.as_slice())
+        });
+        if !subsumed {
+            chosen.push(candidate);
+        }
+        if chosen.len() == max {
+            break;
+        }
+    }
+    chosen
+}
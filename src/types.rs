@@ -1,10 +1,47 @@
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Combo {
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
     pub commands: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// Unix timestamp at which the combo was saved, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<i64>,
+    #[serde(default)]
     pub last_used: i64,
+    /// Number of times the combo has been run.
+    #[serde(default)]
+    pub use_count: u64,
+    /// Time spent running the combo, in seconds, over all its runs.
+    #[serde(default)]
+    pub total_runtime: f64,
+    /// Earlier versions of `commands`, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub revisions: Vec<Revision>,
+    /// Fields written by a newer comboman, kept so they survive a save.
+    #[serde(flatten)]
+    pub extra: serde_yaml::Mapping,
+    /// Project library the combo was loaded from; None for the global one.
+    #[serde(skip)]
+    pub origin: Option<PathBuf>,
+}
+
+/// A version of a combo's commands that was replaced by an edit.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Revision {
+    pub commands: Vec<String>,
+    /// Unix timestamp at which these commands were replaced.
+    pub changed_at: i64,
+    /// Who replaced them, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
 }
 
 pub enum SaveOption {
@@ -13,3 +50,49 @@ pub enum SaveOption {
     SaveAsFunction,
     SaveAsCombo,
 }
+
+/// What to do with an imported combo whose name is already taken.
+pub enum Conflict {
+    Skip,
+    /// Import it under the next free `<name>_<i>`.
+    Rename,
+    /// Replace the existing combo, keeping its usage data.
+    Overwrite,
+}
+
+/// Which version to keep of a combo changed differently here and on the
+/// remote by `comboman sync`.
+pub enum Merge {
+    Mine,
+    Theirs,
+    /// Keep theirs, and mine under the next free `<name>_<i>`.
+    Both,
+}
+
+/// How to deal with a secret found in a stack about to be saved.
+pub enum Redaction {
+    /// Read it from an environment variable when the combo runs.
+    EnvVar,
+    /// Replace it with a `<NAME>` placeholder to fill in by hand.
+    Placeholder,
+    Keep,
+}
+
+/// A single command read from the shell history, plus whatever metadata the
+/// history format records alongside it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HistoryEntry {
+    pub command: String,
+    /// Unix timestamp at which the command was started.
+    pub timestamp: Option<i64>,
+    /// Elapsed running time of the command, in seconds.
+    pub duration: Option<i64>,
+    /// Paths the command referred to (recorded by fish).
+    pub paths: Vec<String>,
+    /// Working directory the command ran in.
+    pub cwd: Option<String>,
+    pub exit_code: Option<i32>,
+    /// Identifier of the shell session that ran the command.
+    pub session: Option<String>,
+    pub hostname: Option<String>,
+}
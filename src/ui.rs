@@ -1,4 +1,8 @@
-use crate::types::{Combo, SaveOption};
+use crate::redact::Finding;
+use crate::store::project_name;
+use crate::suggest::Suggestion;
+use crate::types::{Combo, Conflict, HistoryEntry, Merge, Redaction, SaveOption};
+use chrono::{Local, TimeZone};
 use crossterm::{
     event::{self, Event, KeyCode},
     terminal::{disable_raw_mode, enable_raw_mode},
@@ -6,10 +10,12 @@ use crossterm::{
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
 use ratatui::widgets::ListState;
+use similar::{ChangeTag, TextDiff};
 use ratatui::{
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout},
     style::{Color, Style},
+    text::{Span, Spans, Text},
     widgets::{Block, Borders, List, ListItem, Paragraph},
     Terminal,
 };
@@ -45,38 +51,111 @@ fn setup_terminal() -> Result<RawTerminal, io::Error> {
     Ok(RawTerminal(terminal))
 }
 
+/// Narrows the history pane to entries matching the current context.
+/// The cwd and session filters also hide entries whose source records no
+/// cwd or session, while hiding failed commands keeps those without an exit
+/// code.
+#[derive(Clone, Default)]
+struct ViewFilter {
+    cwd: Option<String>,
+    session: Option<String>,
+    hide_failed: bool,
+}
+
+impl ViewFilter {
+    fn matches(&self, entry: &HistoryEntry) -> bool {
+        if self.cwd.is_some() && entry.cwd != self.cwd {
+            return false;
+        }
+        if self.session.is_some() && entry.session != self.session {
+            return false;
+        }
+        !(self.hide_failed && entry.exit_code.is_some_and(|code| code != 0))
+    }
+
+    fn describe(&self) -> String {
+        let mut active = Vec::new();
+        if self.cwd.is_some() {
+            active.push("cwd");
+        }
+        if self.session.is_some() {
+            active.push("session");
+        }
+        if self.hide_failed {
+            active.push("no failed");
+        }
+        if active.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", active.join(", "))
+        }
+    }
+}
+
+/// The id of the calling shell's session, as exported by comboman's hooks
+/// or by Atuin, whichever the history entries come from.
+fn current_session(history: &[HistoryEntry]) -> Option<String> {
+    ["COMBOMAN_SESSION", "ATUIN_SESSION"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|id| history.iter().any(|e| e.session.as_ref() == Some(id)))
+}
+
 /// Stack-like selection: take a history (newest last) and return selected stack in order.
 /// Controls:
 ///  - Up/Down: move cursor
 ///  - Space: toggle selection mode
 ///  - Left / d: remove current item from stack
-///  - Enter: finalize, return Vec<String>
+///  - c / s: only show commands run in the current directory / shell session
+///  - x: hide commands that failed
+///  - S: select the whole session under the cursor as the stack
+///  - Enter: finalize, return the selected entries
 ///  - Esc: cancel => None
+///
+/// Sessions are runs of timestamped commands with no idle gap longer than
+/// `session_gap` seconds, and are separated by a line in the history pane.
 pub fn select_stack(
-    history: Vec<String>,
+    history: Vec<HistoryEntry>,
     initial_stack: Option<Vec<String>>,
-) -> Option<Vec<String>> {
+    session_gap: i64,
+) -> Option<Vec<HistoryEntry>> {
     if history.is_empty() {
         return None;
     }
+    let sessions = time_sessions(&history, session_gap);
 
     let mut terminal = setup_terminal().unwrap();
-    let mut cursor_idx = history.len() - 1;
-    let mut list_state = ListState::default();
-    list_state.select(Some(cursor_idx));
-
-    let mut selected_indices: Vec<usize> = if let Some(stack) = initial_stack {
-        stack
-            .into_iter()
-            .filter_map(|s| history.iter().position(|h| h == &s))
-            .collect()
-    } else {
-        vec![cursor_idx]
+    let mut filter = ViewFilter::default();
+    // Indices into `history` of the entries passing the filter, and the
+    // cursor position within them.
+    let mut visible: Vec<usize> = (0..history.len()).collect();
+    let mut cursor = visible.len() - 1;
+
+    // Start in normal mode on the latest occurrence of a given stack, so
+    // moving around doesn't change the preselection.
+    let mut selection_mode = initial_stack.is_none();
+    let mut selected_indices: Vec<usize> = match initial_stack {
+        Some(stack) => {
+            let mut indices = Vec::new();
+            let mut before = history.len();
+            for command in stack.iter().rev() {
+                if let Some(i) = history[..before].iter().rposition(|h| &h.command == command) {
+                    indices.push(i);
+                    before = i;
+                }
+            }
+            indices.reverse();
+            indices
+        }
+        None => vec![visible[cursor]],
     };
-    let mut selection_mode = true;
-    if selected_indices.is_empty() {
-        selected_indices.push(cursor_idx);
+    if let Some(&last) = selected_indices.last() {
+        cursor = last;
+    } else {
+        selected_indices.push(visible[cursor]);
     }
+    let mut list_state = ListState::default();
+    list_state.select(Some(cursor));
 
     loop {
         terminal
@@ -94,23 +173,24 @@ pub fn select_stack(
                 };
 
                 // History pane: mark included commands with a check
-                let history_items: Vec<ListItem> = history
+                let history_items: Vec<ListItem> = visible
                     .iter()
                     .enumerate()
-                    .map(|(i, cmd)| {
+                    .map(|(pos, &i)| {
                         let style = if selected_indices.contains(&i) {
                             Style::default().bg(Color::Blue)
                         } else {
                             Style::default()
                         };
-                        ListItem::new(cmd.clone()).style(style)
+                        let new_session = pos > 0 && sessions[visible[pos - 1]] != sessions[i];
+                        history_item(&history[i], new_session).style(style)
                     })
                     .collect();
 
                 let history_list = List::new(history_items)
                     .block(
                         Block::default()
-                            .title(history_title)
+                            .title(format!("{history_title}{}", filter.describe()))
                             .borders(Borders::ALL)
                             .style(Style::default().bg(Color::Black)),
                     )
@@ -124,7 +204,7 @@ pub fn select_stack(
                 sorted_indices.sort();
                 let stack_items: Vec<ListItem> = sorted_indices
                     .iter()
-                    .map(|i| ListItem::new(history[*i].clone()))
+                    .map(|i| ListItem::new(history[*i].command.clone()))
                     .collect();
                 let stack_list = List::new(stack_items).block(
                     Block::default()
@@ -138,23 +218,20 @@ pub fn select_stack(
 
         // Input
         if let Event::Key(key) = event::read().unwrap() {
+            let cursor_idx = visible[cursor];
             match key.code {
-                KeyCode::Up | KeyCode::Char('k') => {
-                    if cursor_idx > 0 {
-                        cursor_idx -= 1;
-                        list_state.select(Some(cursor_idx));
-                        if selection_mode && !selected_indices.contains(&cursor_idx) {
-                            selected_indices.push(cursor_idx);
-                        }
+                KeyCode::Up | KeyCode::Char('k') if cursor > 0 => {
+                    cursor -= 1;
+                    list_state.select(Some(cursor));
+                    if selection_mode && !selected_indices.contains(&visible[cursor]) {
+                        selected_indices.push(visible[cursor]);
                     }
                 }
-                KeyCode::Down | KeyCode::Char('j') => {
-                    if cursor_idx + 1 < history.len() {
-                        cursor_idx += 1;
-                        list_state.select(Some(cursor_idx));
-                        if selection_mode && !selected_indices.contains(&cursor_idx) {
-                            selected_indices.push(cursor_idx);
-                        }
+                KeyCode::Down | KeyCode::Char('j') if cursor + 1 < visible.len() => {
+                    cursor += 1;
+                    list_state.select(Some(cursor));
+                    if selection_mode && !selected_indices.contains(&visible[cursor]) {
+                        selected_indices.push(visible[cursor]);
                     }
                 }
                 KeyCode::Char(' ') | KeyCode::Char('v') => {
@@ -170,17 +247,50 @@ pub fn select_stack(
                 KeyCode::Left | KeyCode::Char('h') => {
                     selected_indices.retain(|&i| i != cursor_idx);
                 }
-                KeyCode::Right | KeyCode::Char('l') => {
-                    if !selected_indices.contains(&cursor_idx) {
-                        selected_indices.push(cursor_idx);
+                KeyCode::Right | KeyCode::Char('l') if !selected_indices.contains(&cursor_idx) => {
+                    selected_indices.push(cursor_idx);
+                }
+                KeyCode::Char(c @ ('c' | 's' | 'x')) => {
+                    let mut next = filter.clone();
+                    match c {
+                        'c' if next.cwd.is_none() => {
+                            next.cwd = std::env::current_dir()
+                                .ok()
+                                .map(|d| d.display().to_string());
+                        }
+                        'c' => next.cwd = None,
+                        's' if next.session.is_none() => {
+                            next.session = current_session(&history);
+                        }
+                        's' => next.session = None,
+                        _ => next.hide_failed = !next.hide_failed,
+                    }
+                    let next_visible: Vec<usize> = (0..history.len())
+                        .filter(|&i| next.matches(&history[i]))
+                        .collect();
+                    // Ignore filters that would leave nothing to select.
+                    if !next_visible.is_empty() {
+                        cursor = next_visible
+                            .iter()
+                            .rposition(|&i| i <= cursor_idx)
+                            .unwrap_or(0);
+                        visible = next_visible;
+                        filter = next;
+                        list_state.select(Some(cursor));
                     }
                 }
-                KeyCode::Enter => {
-                    selected_indices.sort();
-                    let selected_stack: Vec<String> = selected_indices
+                KeyCode::Char('S') if history[cursor_idx].timestamp.is_some() => {
+                    selected_indices = visible
                         .iter()
-                        .map(|i| history[*i].clone())
+                        .copied()
+                        .filter(|&i| sessions[i] == sessions[cursor_idx])
                         .collect();
+                    selection_mode = false;
+                }
+                KeyCode::Enter => {
+                    selected_indices.sort();
+                    let selected_stack: Vec<HistoryEntry> =
+                        selected_indices.iter().map(|i| history[*i].clone()).collect();
                     return Some(selected_stack);
                 }
                 KeyCode::Esc | KeyCode::Char('q') => return None,
@@ -190,6 +300,66 @@ pub fn select_stack(
     }
 }
 
+/// Number each entry by the session it belongs to. A new session starts
+/// after more than `gap` seconds without a command; entries without a
+/// timestamp stay in the current session.
+fn time_sessions(history: &[HistoryEntry], gap: i64) -> Vec<usize> {
+    let mut session = 0;
+    let mut last_time: Option<i64> = None;
+    history
+        .iter()
+        .map(|entry| {
+            if let Some(ts) = entry.timestamp {
+                if last_time.is_some_and(|last| ts - last > gap) {
+                    session += 1;
+                }
+                last_time = Some(ts);
+            }
+            session
+        })
+        .collect()
+}
+
+/// Render a history entry, prefixed with its start time and working
+/// directory and suffixed with a failing exit status, when known.
+/// Entries starting a new session get a separator line above them.
+fn history_item(entry: &HistoryEntry, new_session: bool) -> ListItem<'_> {
+    let dim = Style::default().fg(Color::DarkGray);
+    let mut lines = entry.command.lines();
+    let first = lines.next().unwrap_or_default();
+    let mut spans = Vec::new();
+    if let Some(ts) = entry.timestamp.and_then(|t| Local.timestamp_opt(t, 0).single()) {
+        spans.push(Span::styled(ts.format("%Y-%m-%d %H:%M ").to_string(), dim));
+    }
+    if let Some(cwd) = &entry.cwd {
+        spans.push(Span::styled(format!("{} ", tilde_home(cwd)), dim));
+    }
+    spans.push(Span::raw(first));
+    let mut text = Text::default();
+    if new_session {
+        text.extend([Spans::from(Span::styled("── new session ──", dim))]);
+    }
+    text.extend([Spans::from(spans)]);
+    text.extend(lines.map(|l| Spans::from(Span::raw(l))));
+    if let Some(code) = entry.exit_code.filter(|&c| c != 0) {
+        if let Some(last) = text.lines.last_mut() {
+            last.0.push(Span::styled(format!(" [exit {code}]"), Style::default().fg(Color::Red)));
+        }
+    }
+    ListItem::new(text)
+}
+
+/// Abbreviate the home directory in `path` to `~`.
+fn tilde_home(path: &str) -> String {
+    match dirs::home_dir().and_then(|home| {
+        let home = home.to_string_lossy().into_owned();
+        path.strip_prefix(&home).map(|rest| format!("~{rest}"))
+    }) {
+        Some(short) => short,
+        None => path.to_string(),
+    }
+}
+
 pub fn select_save_option(stack: &[String]) -> Option<SaveOption> {
     let mut terminal = setup_terminal().unwrap();
     let mut list_state = ListState::default();
@@ -264,8 +434,431 @@ pub fn select_save_option(stack: &[String]) -> Option<SaveOption> {
     }
 }
 
+/// Ask what to do with the possible secret `finding` in `stack`.
+/// The secret is highlighted in the stack on the left. Esc keeps it.
+pub fn select_redaction(stack: &[String], finding: &Finding) -> Redaction {
+    let mut terminal = setup_terminal().unwrap();
+    let mut list_state = ListState::default();
+    list_state.select(Some(0));
+
+    let options = [
+        format!("Read it from ${{{}}}", finding.var),
+        format!("Replace it with <{}>", finding.var),
+        "Keep it".to_string(),
+    ];
+
+    loop {
+        terminal
+            .draw(|f| {
+                let chunks = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+                    .split(f.size());
+
+                let stack_items: Vec<ListItem> = stack
+                    .iter()
+                    .enumerate()
+                    .map(|(i, s)| {
+                        if i != finding.line {
+                            return ListItem::new(s.as_str());
+                        }
+                        ListItem::new(Spans::from(vec![
+                            Span::raw(&s[..finding.start]),
+                            Span::styled(
+                                &s[finding.start..finding.end],
+                                Style::default().fg(Color::Red),
+                            ),
+                            Span::raw(&s[finding.end..]),
+                        ]))
+                    })
+                    .collect();
+                let stack_list = List::new(stack_items).block(
+                    Block::default()
+                        .title(format!("Possible secret: {}", finding.kind))
+                        .borders(Borders::ALL)
+                        .style(Style::default().bg(Color::Black)),
+                );
+                f.render_widget(stack_list, chunks[0]);
+
+                let options_items: Vec<ListItem> =
+                    options.iter().map(|o| ListItem::new(o.as_str())).collect();
+                let options_list = List::new(options_items)
+                    .block(
+                        Block::default()
+                            .title("Redact")
+                            .borders(Borders::ALL)
+                            .style(Style::default().bg(Color::Black)),
+                    )
+                    .highlight_style(Style::default().bg(Color::Blue));
+                f.render_stateful_widget(options_list, chunks[1], &mut list_state);
+            })
+            .unwrap();
+
+        if let Event::Key(key) = event::read().unwrap() {
+            match key.code {
+                KeyCode::Up => {
+                    let i = list_state.selected().unwrap_or(0);
+                    if i > 0 {
+                        list_state.select(Some(i - 1));
+                    }
+                }
+                KeyCode::Down => {
+                    let i = list_state.selected().unwrap_or(0);
+                    if i < options.len() - 1 {
+                        list_state.select(Some(i + 1));
+                    }
+                }
+                KeyCode::Enter => {
+                    return match list_state.selected() {
+                        Some(0) => Redaction::EnvVar,
+                        Some(1) => Redaction::Placeholder,
+                        _ => Redaction::Keep,
+                    };
+                }
+                KeyCode::Esc => return Redaction::Keep,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Ask what to do with the imported combo `incoming`, whose name is taken by
+/// `existing`. `renamed` is the name it would be imported under instead.
+/// The left pane shows the incoming commands, or their diff against the
+/// existing ones. Esc cancels the import => None.
+pub fn resolve_conflict(existing: &Combo, incoming: &Combo, renamed: &str) -> Option<Conflict> {
+    let mut terminal = setup_terminal().unwrap();
+    let mut list_state = ListState::default();
+    list_state.select(Some(0));
+    let mut show_diff = false;
+
+    loop {
+        let options = [
+            "Skip".to_string(),
+            format!("Import as '{renamed}'"),
+            "Overwrite".to_string(),
+            if show_diff { "Hide diff" } else { "Show diff" }.to_string(),
+        ];
+        terminal
+            .draw(|f| {
+                let chunks = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+                    .split(f.size());
+
+                let (title, text) = if show_diff {
+                    ("Existing -> incoming", Text::from(diff_lines(&existing.commands, &incoming.commands)))
+                } else {
+                    ("Incoming", Text::from(incoming.commands.join("\n")))
+                };
+                let commands = Paragraph::new(text).block(
+                    Block::default()
+                        .title(format!("{title} '{}'", incoming.name))
+                        .borders(Borders::ALL)
+                        .style(Style::default().bg(Color::Black)),
+                );
+                f.render_widget(commands, chunks[0]);
+
+                let options_items: Vec<ListItem> =
+                    options.iter().map(|o| ListItem::new(o.as_str())).collect();
+                let options_list = List::new(options_items)
+                    .block(
+                        Block::default()
+                            .title("Name already taken")
+                            .borders(Borders::ALL)
+                            .style(Style::default().bg(Color::Black)),
+                    )
+                    .highlight_style(Style::default().bg(Color::Blue));
+                f.render_stateful_widget(options_list, chunks[1], &mut list_state);
+            })
+            .unwrap();
+
+        if let Event::Key(key) = event::read().unwrap() {
+            match key.code {
+                KeyCode::Up => {
+                    let i = list_state.selected().unwrap_or(0);
+                    if i > 0 {
+                        list_state.select(Some(i - 1));
+                    }
+                }
+                KeyCode::Down => {
+                    let i = list_state.selected().unwrap_or(0);
+                    if i < options.len() - 1 {
+                        list_state.select(Some(i + 1));
+                    }
+                }
+                KeyCode::Enter => match list_state.selected() {
+                    Some(0) => return Some(Conflict::Skip),
+                    Some(1) => return Some(Conflict::Rename),
+                    Some(2) => return Some(Conflict::Overwrite),
+                    _ => show_diff = !show_diff,
+                },
+                KeyCode::Esc => return None,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Ask which version to keep of a combo that `comboman sync` found changed
+/// both here (`mine`) and on the remote (`theirs`); None is a side that
+/// deleted it. `renamed` is the name mine would be kept under next to theirs.
+/// The left pane shows my commands, or their diff from theirs. Esc cancels
+/// the sync => None.
+pub fn resolve_merge(mine: Option<&Combo>, theirs: Option<&Combo>, renamed: &str) -> Option<Merge> {
+    let mut terminal = setup_terminal().unwrap();
+    let mut list_state = ListState::default();
+    list_state.select(Some(0));
+    let mut show_diff = false;
+    let name = mine.or(theirs).map(|c| c.name.as_str()).unwrap_or_default();
+    let no_commands = Vec::new();
+    let mine_commands = mine.map_or(&no_commands, |c| &c.commands);
+    let theirs_commands = theirs.map_or(&no_commands, |c| &c.commands);
+
+    loop {
+        let keep_mine = if mine.is_some() { "Keep mine" } else { "Delete it, as I did" };
+        let keep_theirs = if theirs.is_some() { "Keep theirs" } else { "Delete it, as they did" };
+        let mut options = vec![
+            (keep_mine.to_string(), Merge::Mine),
+            (keep_theirs.to_string(), Merge::Theirs),
+        ];
+        if mine.is_some() && theirs.is_some() {
+            options.push((format!("Keep both, mine as '{renamed}'"), Merge::Both));
+        }
+        let toggle = if show_diff { "Hide diff" } else { "Show diff" };
+        terminal
+            .draw(|f| {
+                let chunks = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+                    .split(f.size());
+
+                let (title, text) = match mine {
+                    _ if show_diff => {
+                        ("Theirs -> mine", Text::from(diff_lines(theirs_commands, mine_commands)))
+                    }
+                    Some(mine) => ("Mine", Text::from(mine.commands.join("\n"))),
+                    None => ("Deleted by me", Text::from(theirs_commands.join("\n"))),
+                };
+                let commands = Paragraph::new(text).block(
+                    Block::default()
+                        .title(format!("{title} '{name}'"))
+                        .borders(Borders::ALL)
+                        .style(Style::default().bg(Color::Black)),
+                );
+                f.render_widget(commands, chunks[0]);
+
+                let options_items: Vec<ListItem> = options
+                    .iter()
+                    .map(|(label, _)| ListItem::new(label.as_str()))
+                    .chain([ListItem::new(toggle)])
+                    .collect();
+                let options_list = List::new(options_items)
+                    .block(
+                        Block::default()
+                            .title("Changed here and on the remote")
+                            .borders(Borders::ALL)
+                            .style(Style::default().bg(Color::Black)),
+                    )
+                    .highlight_style(Style::default().bg(Color::Blue));
+                f.render_stateful_widget(options_list, chunks[1], &mut list_state);
+            })
+            .unwrap();
+
+        if let Event::Key(key) = event::read().unwrap() {
+            match key.code {
+                KeyCode::Up => {
+                    let i = list_state.selected().unwrap_or(0);
+                    if i > 0 {
+                        list_state.select(Some(i - 1));
+                    }
+                }
+                KeyCode::Down => {
+                    let i = list_state.selected().unwrap_or(0);
+                    if i < options.len() {
+                        list_state.select(Some(i + 1));
+                    }
+                }
+                KeyCode::Enter => {
+                    let i = list_state.selected().unwrap_or(0);
+                    if i < options.len() {
+                        return Some(options.swap_remove(i).1);
+                    }
+                    show_diff = !show_diff;
+                }
+                KeyCode::Esc => return None,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Line diff from `old` to `new` commands: removed lines in red prefixed
+/// with `-`, added ones in green prefixed with `+`.
+pub fn diff_lines(old: &[String], new: &[String]) -> Vec<Spans<'static>> {
+    diff_text(old, new)
+        .into_iter()
+        .map(|line| {
+            let style = match line.chars().next() {
+                Some('-') => Style::default().fg(Color::Red),
+                Some('+') => Style::default().fg(Color::Green),
+                _ => Style::default(),
+            };
+            Spans::from(Span::styled(line, style))
+        })
+        .collect()
+}
+
+/// Line diff from `old` to `new` commands as plain text, each line prefixed
+/// with `-`, `+` or a space.
+pub fn diff_text(old: &[String], new: &[String]) -> Vec<String> {
+    let old = old.join("\n");
+    let new = new.join("\n");
+    TextDiff::from_lines(&old, &new)
+        .iter_all_changes()
+        .map(|change| {
+            let sign = match change.tag() {
+                ChangeTag::Delete => "-",
+                ChangeTag::Insert => "+",
+                ChangeTag::Equal => " ",
+            };
+            format!("{sign} {}", change.value().trim_end_matches('\n'))
+        })
+        .collect()
+}
+
+/// Pick one of the `suggestions` mined from `history`.
+/// Left pane lists them by frequency, right pane previews the most recent
+/// occurrence. Up/Down moves; Enter selects; Esc cancels.
+pub fn select_suggestion(suggestions: &[Suggestion], history: &[HistoryEntry]) -> Option<usize> {
+    let mut terminal = setup_terminal().unwrap();
+    let mut list_state = ListState::default();
+    list_state.select(Some(0));
+
+    loop {
+        terminal
+            .draw(|f| {
+                let chunks = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+                    .split(f.size());
+
+                let items: Vec<ListItem> = suggestions
+                    .iter()
+                    .map(|s| ListItem::new(format!("{}x  {}", s.count, s.shape.join(" -> "))))
+                    .collect();
+                let list = List::new(items)
+                    .block(
+                        Block::default()
+                            .title("Repeated Sequences")
+                            .borders(Borders::ALL)
+                            .style(Style::default().bg(Color::Black)),
+                    )
+                    .highlight_symbol(">>")
+                    .highlight_style(Style::default().bg(Color::Blue));
+                f.render_stateful_widget(list, chunks[0], &mut list_state);
+
+                let preview_text = list_state
+                    .selected()
+                    .and_then(|i| suggestions.get(i))
+                    .map(|s| {
+                        s.latest
+                            .iter()
+                            .map(|&i| history[i].command.as_str())
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    })
+                    .unwrap_or_default();
+                let preview = Paragraph::new(preview_text).block(
+                    Block::default()
+                        .title("Most Recent")
+                        .borders(Borders::ALL)
+                        .style(Style::default().bg(Color::Black)),
+                );
+                f.render_widget(preview, chunks[1]);
+            })
+            .unwrap();
+
+        if let Event::Key(key) = event::read().unwrap() {
+            let i = list_state.selected().unwrap_or(0);
+            match key.code {
+                KeyCode::Up | KeyCode::Char('k') if i > 0 => list_state.select(Some(i - 1)),
+                KeyCode::Down | KeyCode::Char('j') if i + 1 < suggestions.len() => {
+                    list_state.select(Some(i + 1))
+                }
+                KeyCode::Enter => return Some(i),
+                KeyCode::Esc | KeyCode::Char('q') => return None,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Whether `combo` matches the fuzzy finder `query`. Words starting with `#`
+/// must each be the start of one of the combo's tags; the rest of the query
+/// is fuzzy matched against the name.
+fn combo_matches(combo: &Combo, query: &str, matcher: &SkimMatcherV2) -> bool {
+    let (tags, words): (Vec<&str>, Vec<&str>) =
+        query.split_whitespace().partition(|w| w.starts_with('#'));
+    let tagged = tags.iter().all(|t| {
+        let t = t[1..].to_lowercase();
+        combo.tags.iter().any(|tag| tag.to_lowercase().starts_with(&t))
+    });
+    tagged && matcher.fuzzy_match(&combo.name, &words.join(" ")).is_some()
+}
+
+/// Preview of a combo: its description, tags, notes and usage, then its
+/// commands.
+fn combo_preview(combo: &Combo) -> Text<'static> {
+    let dim = Style::default().fg(Color::DarkGray);
+    let mut lines: Vec<Spans> = Vec::new();
+    if let Some(description) = &combo.description {
+        lines.push(Spans::from(description.clone()));
+    }
+    if !combo.tags.is_empty() {
+        let tags: Vec<String> = combo.tags.iter().map(|t| format!("#{t}")).collect();
+        lines.push(Spans::from(Span::styled(tags.join(" "), Style::default().fg(Color::Cyan))));
+    }
+    if let Some(notes) = &combo.notes {
+        lines.extend(notes.lines().map(|l| Spans::from(Span::styled(l.to_string(), dim))));
+    }
+    lines.push(Spans::from(Span::styled(usage_summary(combo), dim)));
+    lines.push(Spans::default());
+    lines.extend(combo.commands.iter().flat_map(|c| c.lines()).map(|l| Spans::from(l.to_string())));
+    Text::from(lines)
+}
+
+/// One line on how often and how recently `combo` was used.
+pub fn usage_summary(combo: &Combo) -> String {
+    let last = match Local.timestamp_opt(combo.last_used, 0).single() {
+        Some(t) if combo.last_used > 0 => t.format("%Y-%m-%d %H:%M").to_string(),
+        _ => "never".to_string(),
+    };
+    let mut summary = format!("run {} times, last used {last}", combo.use_count);
+    if combo.use_count > 0 {
+        summary.push_str(&format!(", {} in total", format_runtime(combo.total_runtime)));
+    }
+    if let Some(created) = combo.created_at.and_then(|t| Local.timestamp_opt(t, 0).single()) {
+        summary.push_str(&format!(", created {}", created.format("%Y-%m-%d")));
+    }
+    summary
+}
+
+/// Format a duration in seconds compactly, e.g. `4.2s`, `3m 05s`, `1h 02m`.
+fn format_runtime(seconds: f64) -> String {
+    let whole = seconds as u64;
+    if whole < 60 {
+        format!("{seconds:.1}s")
+    } else if whole < 3600 {
+        format!("{}m {:02}s", whole / 60, whole % 60)
+    } else {
+        format!("{}h {:02}m", whole / 3600, whole % 3600 / 60)
+    }
+}
+
 /// UI for selecting an existing combo from `combos`.
-/// Shows left pane list (filterable with fuzzy search) and right pane preview.
+/// Shows left pane list (filterable with fuzzy search, `#tag` words filter
+/// by tag) and right pane preview.
 /// Typing filters; Backspace clears characters; Up/Down moves; Enter selects; Esc cancels.
 pub fn run_ui(mut combos: Vec<Combo>) -> Option<String> {
     if combos.is_empty() {
@@ -273,8 +866,8 @@ pub fn run_ui(mut combos: Vec<Combo>) -> Option<String> {
         return None;
     }
 
-    // sort combos by last_used descending (most recent first)
-    combos.sort_by(|a, b| b.last_used.cmp(&a.last_used));
+    // project combos first, then by last_used descending (most recent first)
+    combos.sort_by_key(|c| (c.origin.is_none(), std::cmp::Reverse(c.last_used)));
 
     let matcher = SkimMatcherV2::default();
     let mut filtered = combos.clone();
@@ -296,7 +889,13 @@ pub fn run_ui(mut combos: Vec<Combo>) -> Option<String> {
 
                 let items: Vec<ListItem> = filtered
                     .iter()
-                    .map(|c| ListItem::new(c.name.clone()))
+                    .map(|c| match c.origin.as_deref().map(project_name) {
+                        Some(project) => ListItem::new(Spans::from(vec![
+                            Span::styled(format!("[{project}] "), Style::default().fg(Color::DarkGray)),
+                            Span::raw(c.name.as_str()),
+                        ])),
+                        None => ListItem::new(c.name.clone()),
+                    })
                     .collect();
                 let list = List::new(items)
                     .block(
@@ -309,10 +908,7 @@ pub fn run_ui(mut combos: Vec<Combo>) -> Option<String> {
                     .highlight_style(Style::default().bg(Color::Blue));
                 f.render_stateful_widget(list, chunks[0], &mut list_state);
 
-                let preview_text = filtered
-                    .get(selected_idx)
-                    .map(|c| c.commands.join("\n"))
-                    .unwrap_or_default();
+                let preview_text = filtered.get(selected_idx).map(combo_preview).unwrap_or_default();
                 let preview = Paragraph::new(preview_text).block(
                     Block::default()
                         .title("Preview")
@@ -325,23 +921,19 @@ pub fn run_ui(mut combos: Vec<Combo>) -> Option<String> {
 
         if let Event::Key(key) = event::read().unwrap() {
             match key.code {
-                KeyCode::Up => {
-                    if selected_idx > 0 {
-                        selected_idx -= 1;
-                        list_state.select(Some(selected_idx));
-                    }
+                KeyCode::Up if selected_idx > 0 => {
+                    selected_idx -= 1;
+                    list_state.select(Some(selected_idx));
                 }
-                KeyCode::Down => {
-                    if selected_idx + 1 < filtered.len() {
-                        selected_idx += 1;
-                        list_state.select(Some(selected_idx));
-                    }
+                KeyCode::Down if selected_idx + 1 < filtered.len() => {
+                    selected_idx += 1;
+                    list_state.select(Some(selected_idx));
                 }
                 KeyCode::Char(c) => {
                     filter_input.push(c);
                     filtered = combos
                         .iter()
-                        .filter(|combo| matcher.fuzzy_match(&combo.name, &filter_input).is_some())
+                        .filter(|combo| combo_matches(combo, &filter_input, &matcher))
                         .cloned()
                         .collect();
                     selected_idx = 0;
@@ -351,7 +943,7 @@ pub fn run_ui(mut combos: Vec<Combo>) -> Option<String> {
                     filter_input.pop();
                     filtered = combos
                         .iter()
-                        .filter(|combo| matcher.fuzzy_match(&combo.name, &filter_input).is_some())
+                        .filter(|combo| combo_matches(combo, &filter_input, &matcher))
                         .cloned()
                         .collect();
                     selected_idx = 0;